@@ -0,0 +1,103 @@
+#[cfg(feature = "check-assets")]
+use anyhow::Context;
+use anyhow::{bail, Result};
+use presence_bridge_core::AppConfig;
+#[cfg(feature = "check-assets")]
+use serde::Deserialize;
+#[cfg(feature = "check-assets")]
+use std::time::Duration;
+
+#[cfg(feature = "check-assets")]
+const ASSETS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Art asset keys the config refers to, in config order without duplicates.
+pub fn referenced_keys(cfg: &AppConfig) -> Vec<String> {
+    let assets = &cfg.assets;
+    let large = assets
+        .large_image
+        .iter()
+        .flat_map(|choice| choice.keys())
+        .chain(&assets.large_image_paused);
+    let small = [&assets.small_play_image, &assets.small_pause_image]
+        .into_iter()
+        .flatten();
+
+    let mut keys: Vec<String> = Vec::new();
+    for key in large.chain(small) {
+        if !key.is_empty() && !key.starts_with("http") && !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+    keys
+}
+
+#[cfg(feature = "check-assets")]
+#[derive(Deserialize)]
+struct UploadedAsset {
+    name: String,
+}
+
+#[cfg(feature = "check-assets")]
+fn parse_asset_names(body: &str) -> Result<Vec<String>> {
+    let assets: Vec<UploadedAsset> =
+        serde_json::from_str(body).context("unexpected response from the Discord assets API")?;
+    Ok(assets.into_iter().map(|a| a.name).collect())
+}
+
+/// Lists the art asset names uploaded to `app_id`. Blocking; `bot_token` is sent when set.
+#[cfg(feature = "check-assets")]
+pub fn fetch_uploaded_keys(app_id: &str, bot_token: Option<&str>) -> Result<Vec<String>> {
+    if app_id.is_empty() || !app_id.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("discord_app_id must be set to a numeric application id");
+    }
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(ASSETS_TIMEOUT))
+        .build()
+        .into();
+    let mut request = agent.get(format!(
+        "https://discord.com/api/v10/oauth2/applications/{app_id}/assets"
+    ));
+    if let Some(token) = bot_token {
+        request = request.header("Authorization", format!("Bot {token}"));
+    }
+    let body = request
+        .call()
+        .context("failed to query the Discord assets API")?
+        .body_mut()
+        .read_to_string()
+        .context("failed to read the Discord assets API response")?;
+    parse_asset_names(&body)
+}
+
+/// Builds without the `check-assets` feature carry no HTTP client.
+#[cfg(not(feature = "check-assets"))]
+pub fn fetch_uploaded_keys(_app_id: &str, _bot_token: Option<&str>) -> Result<Vec<String>> {
+    bail!("doctor --check-assets needs a build with the check-assets feature")
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "check-assets")]
+    use super::parse_asset_names;
+    use super::referenced_keys;
+    use presence_bridge_core::{AppConfig, AssetChoice};
+
+    #[test]
+    fn collects_referenced_keys_once() {
+        let mut cfg = AppConfig::default();
+        cfg.assets.large_image = Some(AssetChoice::Rotate(
+            ["cover_a", "play", "cover_a"].map(String::from).to_vec(),
+        ));
+        cfg.assets.small_pause_image = Some("https://example.com/pause.png".to_string());
+
+        assert_eq!(referenced_keys(&cfg), ["cover_a", "play"]);
+    }
+
+    #[cfg(feature = "check-assets")]
+    #[test]
+    fn parses_asset_names() {
+        let body = r#"[{"id":"1","type":1,"name":"app_icon"},{"id":"2","type":1,"name":"play"}]"#;
+        assert_eq!(parse_asset_names(body).unwrap(), ["app_icon", "play"]);
+        assert!(parse_asset_names(r#"{"message":"Unknown Application"}"#).is_err());
+    }
+}
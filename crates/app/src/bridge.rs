@@ -0,0 +1,1190 @@
+use crate::assets;
+use crate::config_file::load_or_default;
+use crate::focus::{FocusGate, FocusTransition};
+use crate::history::{self, History};
+use crate::idle::{IdleGate, IdleTransition};
+use crate::notify;
+use crate::presence_override::{OverrideChange, PresenceOverride};
+use crate::rate_limit::SendLimiter;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use presence_bridge_core::AppConfig;
+use presence_bridge_discord_rpc::{
+    ConnectionEvent, DiscordClosed, DiscordRpcClient, ReconnectLimitReached,
+};
+use presence_bridge_engine::{DiffKind, EngineAction, EngineConfig, EngineOutput, EventEngine};
+use presence_bridge_providers::focus::system_foreground_source;
+use presence_bridge_providers::idle::system_idle_source;
+use presence_bridge_providers::{
+    build_provider_chain, build_single_provider_chain, provider_config_changed, ProviderChain,
+    ProviderSnapshot,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc, Notify};
+use tracing::{debug, error, info, trace, warn, Level};
+
+const SNAPSHOT_CHANNEL_CAPACITY: usize = 16;
+
+/// Receives the engine's decisions; the default sink talks to Discord.
+#[async_trait]
+pub trait PresenceSink: Send {
+    async fn apply(&mut self, action: EngineAction) -> Result<()>;
+
+    /// Probes the connection while no updates are flowing.
+    async fn health_check(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the configuration file is reloaded.
+    fn reload(&mut self, _cfg: &AppConfig) {}
+}
+
+pub struct DiscordSink {
+    client: DiscordRpcClient,
+    /// 0 is `discord_app_id`; n is `secondary_discord_app_ids[n - 1]`.
+    slot: usize,
+    dry_run: bool,
+}
+
+impl DiscordSink {
+    pub fn new(cfg: &AppConfig, dry_run: bool) -> Self {
+        Self::for_slot(cfg, 0, dry_run, None)
+    }
+
+    /// One sink per configured application, fanned out when secondaries are set.
+    pub fn for_all_apps(cfg: &AppConfig, dry_run: bool) -> Box<dyn PresenceSink> {
+        Self::for_all_apps_reporting(cfg, dry_run, None)
+    }
+
+    /// Like [`DiscordSink::for_all_apps`], also forwarding the primary application's
+    /// connection changes to `events`.
+    pub fn for_all_apps_reporting(
+        cfg: &AppConfig,
+        dry_run: bool,
+        events: Option<broadcast::Sender<ConnectionEvent>>,
+    ) -> Box<dyn PresenceSink> {
+        if cfg.secondary_discord_app_ids.is_empty() {
+            return Box::new(Self::for_slot(cfg, 0, dry_run, events));
+        }
+        let sinks = (0..=cfg.secondary_discord_app_ids.len())
+            .map(|slot| {
+                let events = if slot == 0 { events.clone() } else { None };
+                Box::new(Self::for_slot(cfg, slot, dry_run, events)) as Box<dyn PresenceSink>
+            })
+            .collect();
+        Box::new(FanOutSink::new(sinks))
+    }
+
+    fn for_slot(
+        cfg: &AppConfig,
+        slot: usize,
+        dry_run: bool,
+        events: Option<broadcast::Sender<ConnectionEvent>>,
+    ) -> Self {
+        let app_id = app_id_for_slot(cfg, slot).unwrap_or_default();
+        let mut client = DiscordRpcClient::new(app_id);
+        client.set_preferred_flavor(cfg.discord_flavor);
+        client.set_transport(cfg.discord_transport);
+        client.set_ws_endpoint(&cfg.discord_ws_host, cfg.discord_ws_proxy.as_deref());
+        client.set_max_reconnect_attempts(cfg.discord_max_reconnect_attempts);
+        client.set_on_connection_change(Box::new(move |event| {
+            match event {
+                ConnectionEvent::Connected { .. } => {}
+                ConnectionEvent::Disconnected => warn!("lost connection to discord"),
+                ConnectionEvent::BackingOff { until } => debug!(
+                    retry_in_ms =
+                        until.saturating_duration_since(Instant::now()).as_millis() as u64,
+                    "discord unreachable; backing off"
+                ),
+            }
+            if let Some(events) = &events {
+                let _ = events.send(event);
+            }
+        }));
+        Self {
+            client,
+            slot,
+            dry_run,
+        }
+    }
+}
+
+#[async_trait]
+impl PresenceSink for DiscordSink {
+    async fn apply(&mut self, action: EngineAction) -> Result<()> {
+        match action {
+            EngineAction::Send(state) => {
+                if self.dry_run {
+                    info!(details = %state.details, state = %state.state, "dry-run: would set activity");
+                    return Ok(());
+                }
+                self.client
+                    .set_activity(&state)
+                    .await
+                    .context("discord rpc set_activity failed")
+            }
+            EngineAction::Clear => {
+                if self.dry_run {
+                    info!("dry-run: would clear activity");
+                    return Ok(());
+                }
+                self.client
+                    .clear_activity()
+                    .await
+                    .context("discord rpc clear_activity failed")
+            }
+            EngineAction::None => Ok(()),
+        }
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.client.ping().await.context("discord rpc ping failed")
+    }
+
+    fn reload(&mut self, cfg: &AppConfig) {
+        // Adding or removing secondary apps takes effect on restart.
+        if let Some(app_id) = app_id_for_slot(cfg, self.slot) {
+            self.client.update_client_id(app_id);
+        }
+        self.client.set_preferred_flavor(cfg.discord_flavor);
+        self.client.set_transport(cfg.discord_transport);
+        self.client
+            .set_ws_endpoint(&cfg.discord_ws_host, cfg.discord_ws_proxy.as_deref());
+        self.client
+            .set_max_reconnect_attempts(cfg.discord_max_reconnect_attempts);
+    }
+}
+
+fn app_id_for_slot(cfg: &AppConfig, slot: usize) -> Option<String> {
+    match slot {
+        0 => Some(cfg.discord_app_id.clone()),
+        n => cfg.secondary_discord_app_ids.get(n - 1).cloned(),
+    }
+}
+
+/// Applies every action to each inner sink; a failing sink doesn't stop the others.
+pub struct FanOutSink {
+    sinks: Vec<Box<dyn PresenceSink>>,
+}
+
+impl FanOutSink {
+    pub fn new(sinks: Vec<Box<dyn PresenceSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+#[async_trait]
+impl PresenceSink for FanOutSink {
+    async fn apply(&mut self, action: EngineAction) -> Result<()> {
+        let mut first_err = None;
+        for (idx, sink) in self.sinks.iter_mut().enumerate() {
+            if let Err(err) = sink.apply(action.clone()).await {
+                debug!(sink = idx, error = %err, "presence sink failed");
+                first_err.get_or_insert(err);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        let mut first_err = None;
+        for sink in &mut self.sinks {
+            if let Err(err) = sink.health_check().await {
+                first_err.get_or_insert(err);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    fn reload(&mut self, cfg: &AppConfig) {
+        for sink in &mut self.sinks {
+            sink.reload(cfg);
+        }
+    }
+}
+
+/// Stops a running [`PresenceBridge`] from another task.
+#[derive(Clone)]
+pub struct StopHandle(Arc<Notify>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.notify_one();
+    }
+}
+
+#[derive(Default)]
+pub struct PresenceBridgeBuilder {
+    cfg: Option<AppConfig>,
+    cfg_path: Option<PathBuf>,
+    provider: Option<String>,
+    chain: Option<ProviderChain>,
+    sink: Option<Box<dyn PresenceSink>>,
+    dry_run: bool,
+    reload_signals: Option<mpsc::Receiver<()>>,
+}
+
+impl PresenceBridgeBuilder {
+    pub fn config(mut self, cfg: AppConfig) -> Self {
+        self.cfg = Some(cfg);
+        self
+    }
+
+    /// Watches this file for reloads and keeps track history next to it.
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cfg_path = Some(path.into());
+        self
+    }
+
+    /// Uses only the named provider instead of `provider_priority`.
+    pub fn provider(mut self, name: impl Into<String>) -> Self {
+        self.provider = Some(name.into());
+        self
+    }
+
+    /// Uses a prebuilt chain; it is kept as-is across config reloads.
+    pub fn provider_chain(mut self, chain: ProviderChain) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub fn sink(mut self, sink: impl PresenceSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Reloads the config file whenever `signals` yields, e.g. on SIGHUP; the file is
+    /// also watched for changes either way.
+    pub fn reload_signals(mut self, signals: mpsc::Receiver<()>) -> Self {
+        self.reload_signals = Some(signals);
+        self
+    }
+
+    /// Logs presence updates instead of sending them; ignored with a custom sink.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn build(self) -> Result<PresenceBridge> {
+        let cfg = self.cfg.unwrap_or_default();
+        let fixed_chain = self.chain.is_some();
+        let chain = match self.chain {
+            Some(chain) => chain,
+            None => build_chain(&cfg, self.provider.as_deref())?,
+        };
+        let (connection, _) = broadcast::channel(SNAPSHOT_CHANNEL_CAPACITY);
+        let sink = self.sink.unwrap_or_else(|| {
+            DiscordSink::for_all_apps_reporting(&cfg, self.dry_run, Some(connection.clone()))
+        });
+        let (snapshots, _) = broadcast::channel(SNAPSHOT_CHANNEL_CAPACITY);
+        let (outputs, _) = broadcast::channel(SNAPSHOT_CHANNEL_CAPACITY);
+
+        Ok(PresenceBridge {
+            engine: EventEngine::new(EngineConfig::from_app_config(&cfg)),
+            history: History::new(cfg.history_size),
+            idle_gate: build_idle_gate(&cfg),
+            focus_gate: build_focus_gate(&cfg),
+            presence_override: cfg.override_file.clone().map(PresenceOverride::new),
+            limiter: SendLimiter::new(
+                cfg.send_limit.max_sends,
+                Duration::from_millis(cfg.send_limit.window_ms),
+            ),
+            cfg,
+            cfg_path: self.cfg_path,
+            provider: self.provider,
+            chain,
+            fixed_chain,
+            sink,
+            snapshots,
+            outputs,
+            connection,
+            stop: Arc::new(Notify::new()),
+            reload_signals: self.reload_signals,
+            warned_fallback: false,
+            last_applied_at: Instant::now(),
+            gave_up: None,
+        })
+    }
+}
+
+/// The provider chain, event engine and presence sink wired into one polling loop.
+pub struct PresenceBridge {
+    cfg: AppConfig,
+    cfg_path: Option<PathBuf>,
+    provider: Option<String>,
+    chain: ProviderChain,
+    fixed_chain: bool,
+    engine: EventEngine,
+    sink: Box<dyn PresenceSink>,
+    history: History,
+    idle_gate: Option<IdleGate>,
+    focus_gate: Option<FocusGate>,
+    presence_override: Option<PresenceOverride>,
+    limiter: SendLimiter,
+    snapshots: broadcast::Sender<ProviderSnapshot>,
+    outputs: broadcast::Sender<EngineOutput>,
+    connection: broadcast::Sender<ConnectionEvent>,
+    stop: Arc<Notify>,
+    reload_signals: Option<mpsc::Receiver<()>>,
+    warned_fallback: bool,
+    last_applied_at: Instant,
+    /// Set once the sink has given up reconnecting; ends `run` with that error.
+    gave_up: Option<anyhow::Error>,
+}
+
+impl PresenceBridge {
+    pub fn builder() -> PresenceBridgeBuilder {
+        PresenceBridgeBuilder::default()
+    }
+
+    /// Observes every provider snapshot polled from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProviderSnapshot> {
+        self.snapshots.subscribe()
+    }
+
+    /// Observes every engine decision from now on.
+    pub fn subscribe_outputs(&self) -> broadcast::Receiver<EngineOutput> {
+        self.outputs.subscribe()
+    }
+
+    /// Observes Discord connection changes; silent when a custom sink is used.
+    pub fn subscribe_connection(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.connection.subscribe()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.stop.clone())
+    }
+
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+
+    /// Polls until stopped, then clears whatever presence is shown. Fails once Discord
+    /// stays unreachable past `discord_max_reconnect_attempts`.
+    pub async fn run(&mut self) -> Result<()> {
+        log_startup_summary(&self.cfg, &self.chain.provider_names());
+
+        let (reload_tx, mut reload_rx) = mpsc::channel::<()>(4);
+        if let Some(path) = &self.cfg_path {
+            spawn_config_watcher(
+                path.clone(),
+                self.cfg.intervals.file_watch_poll_ms,
+                reload_tx.clone(),
+            );
+        }
+        if let Some(signals) = self.reload_signals.take() {
+            forward_reload_signals(signals, reload_tx);
+        }
+
+        let stop = self.stop.clone();
+        let mut next_poll_in = Duration::from_secs(0);
+        let mut health = health_check_interval(&self.cfg);
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(next_poll_in) => {
+                    next_poll_in = self.step().await;
+                    if let Some(err) = self.gave_up.take() {
+                        return Err(err);
+                    }
+                }
+                _ = tick_or_pending(health.as_mut()) => {
+                    self.check_health().await;
+                }
+                Some(()) = reload_rx.recv() => {
+                    let coalesced = drain_pending(&mut reload_rx);
+                    if coalesced > 0 {
+                        debug!(coalesced, "coalesced pending reload signals");
+                    }
+                    if self.reload() {
+                        next_poll_in = Duration::from_secs(0);
+                        health = health_check_interval(&self.cfg);
+                    }
+                }
+                _ = stop.notified() => {
+                    info!("shutting down");
+                    let action = self.engine.shutdown();
+                    if let Err(err) = self.sink.apply(action).await {
+                        debug!(error=%err, "failed to clear presence on shutdown");
+                    }
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs one poll, engine tick and sink update; returns the delay until the next poll.
+    pub async fn step(&mut self) -> Duration {
+        let idle_state = match self.idle_gate.as_mut() {
+            Some(gate) => gate.check().await,
+            None => IdleTransition::Active,
+        };
+        if idle_state == IdleTransition::Resumed {
+            info!("user active again; resuming presence");
+            self.engine.force_resend();
+        }
+        let focus_state = match self.focus_gate.as_mut() {
+            Some(gate) => gate.check().await,
+            None => FocusTransition::Focused,
+        };
+        if focus_state == FocusTransition::Refocused {
+            info!("watched app focused again; resuming presence");
+            self.engine.force_resend();
+        }
+
+        let mut override_removed = false;
+        if let Some(takeover) = self.presence_override.as_mut() {
+            let keepalive = Duration::from_millis(self.cfg.intervals.presence_keepalive_ms);
+            let action = match takeover.check() {
+                OverrideChange::Set(state) => EngineAction::Send(state),
+                OverrideChange::Removed => {
+                    self.engine.force_resend();
+                    override_removed = true;
+                    EngineAction::None
+                }
+                OverrideChange::Unchanged => takeover
+                    .resend_due(Instant::now(), keepalive)
+                    .map_or(EngineAction::None, EngineAction::Send),
+            };
+            if takeover.is_active() {
+                if !self.send(action).await {
+                    if let Some(takeover) = self.presence_override.as_mut() {
+                        takeover.send_failed();
+                    }
+                }
+                return Duration::from_millis(self.cfg.intervals.playing_poll_ms);
+            }
+        }
+
+        let snapshot = self.chain.poll_best().await;
+        warn_fallback_once(&mut self.warned_fallback, &snapshot);
+        let _ = self.snapshots.send(snapshot.clone());
+        let track = snapshot.track.clone();
+        let out = self
+            .engine
+            .tick(snapshot, Instant::now(), SystemTime::now());
+        debug!(
+            reason = ?out.reason,
+            diff = ?out.diff,
+            next_poll_ms = out.next_poll_in.as_millis() as u64,
+            "engine decision"
+        );
+        if tracing::enabled!(Level::TRACE) {
+            if let Ok(json) = serde_json::to_string(&out) {
+                trace!(output = %json, "engine output");
+            }
+        }
+        let _ = self.outputs.send(out.clone());
+
+        if let (DiffKind::TrackChanged, Some(track)) = (out.diff, track.as_ref()) {
+            if self.cfg.notify_on_track_change {
+                notify::show(notify::track_notification(track));
+            }
+            self.history.record(track, SystemTime::now());
+            if let Some(path) = &self.cfg_path {
+                if let Err(err) = self.history.save(&history::history_path(path)).await {
+                    debug!(error=%err, "failed to persist track history");
+                }
+            }
+        }
+
+        let action = match idle_state {
+            IdleTransition::BecameIdle => {
+                info!("user idle; clearing presence");
+                EngineAction::Clear
+            }
+            // A removed takeover still has to come down when nothing replaces it.
+            IdleTransition::StillIdle if override_removed => EngineAction::Clear,
+            IdleTransition::StillIdle => EngineAction::None,
+            IdleTransition::Active | IdleTransition::Resumed => match (focus_state, out.action) {
+                (FocusTransition::LostFocus, _) => {
+                    info!("watched app lost focus; clearing presence");
+                    EngineAction::Clear
+                }
+                (FocusTransition::StillUnfocused, _) | (_, EngineAction::None)
+                    if override_removed =>
+                {
+                    EngineAction::Clear
+                }
+                (FocusTransition::StillUnfocused, _) => EngineAction::None,
+                (_, action) => action,
+            },
+        };
+        self.send(action).await;
+        out.next_poll_in
+    }
+
+    /// Passes an action through the send limiter to the sink; false when the sink failed.
+    async fn send(&mut self, action: EngineAction) -> bool {
+        let action = self.limiter.admit(action, Instant::now());
+        if !matches!(action, EngineAction::None) {
+            self.last_applied_at = Instant::now();
+        }
+        if let Err(err) = self.sink.apply(action).await {
+            if err.chain().any(|cause| cause.is::<ReconnectLimitReached>()) {
+                self.gave_up = Some(err);
+                return false;
+            }
+            if err.chain().any(|cause| cause.is::<DiscordClosed>()) {
+                info!(
+                    error = %format!("{err:#}"),
+                    "discord is not running; will reconnect when it is back"
+                );
+                return false;
+            }
+            warn!(error=%err, "discord rpc update failed; will retry with backoff");
+            return false;
+        }
+        true
+    }
+
+    /// Pings Discord only when nothing was sent for a whole health-check interval.
+    async fn check_health(&mut self) {
+        let interval = Duration::from_millis(self.cfg.intervals.health_check_ms);
+        if self.last_applied_at.elapsed() < interval {
+            return;
+        }
+        if let Err(err) = self.sink.health_check().await {
+            info!(error=%err, "discord health check failed; reconnecting on the next update");
+        }
+    }
+
+    fn reload(&mut self) -> bool {
+        let Some(path) = &self.cfg_path else {
+            return false;
+        };
+        let cfg = match load_or_default(path) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                error!(error=%err, "failed to reload config");
+                return false;
+            }
+        };
+
+        self.engine
+            .update_config(EngineConfig::from_app_config(&cfg));
+        self.history.set_capacity(cfg.history_size);
+        self.limiter.configure(
+            cfg.send_limit.max_sends,
+            Duration::from_millis(cfg.send_limit.window_ms),
+        );
+        match (self.idle_gate.as_mut(), cfg.idle.enabled) {
+            (Some(gate), true) => {
+                gate.set_threshold(Duration::from_millis(cfg.idle.threshold_ms));
+            }
+            _ => self.idle_gate = build_idle_gate(&cfg),
+        }
+        match (self.focus_gate.as_mut(), cfg.focus.enabled) {
+            (Some(gate), true) => gate.set_apps(&cfg.focus.apps),
+            _ => self.focus_gate = build_focus_gate(&cfg),
+        }
+        if cfg.override_file != self.cfg.override_file {
+            self.presence_override = cfg.override_file.clone().map(PresenceOverride::new);
+        }
+        self.sink.reload(&cfg);
+        // Keep the chain, and any connections its providers hold, unless its inputs changed.
+        if !self.fixed_chain && provider_config_changed(&self.cfg, &cfg) {
+            match build_chain(&cfg, self.provider.as_deref()) {
+                Ok(chain) => self.chain = chain,
+                Err(err) => error!(error=%err, "failed to rebuild provider chain"),
+            }
+        }
+        self.cfg = cfg;
+        info!("configuration reloaded");
+        true
+    }
+}
+
+pub(crate) fn build_chain(cfg: &AppConfig, provider: Option<&str>) -> Result<ProviderChain> {
+    match provider {
+        Some(name) => build_single_provider_chain(cfg, name),
+        None => Ok(build_provider_chain(cfg)),
+    }
+}
+
+fn health_check_interval(cfg: &AppConfig) -> Option<tokio::time::Interval> {
+    let period = Duration::from_millis(cfg.intervals.health_check_ms);
+    if period.is_zero() {
+        return None;
+    }
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    Some(interval)
+}
+
+async fn tick_or_pending(interval: Option<&mut tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// One line with the effective settings, so a pasted log answers the usual questions.
+fn log_startup_summary(cfg: &AppConfig, providers: &[&'static str]) {
+    info!(
+        discord_app_id = %mask_app_id(&cfg.discord_app_id),
+        secondary_apps = cfg.secondary_discord_app_ids.len(),
+        transport = ?cfg.discord_transport,
+        flavor = ?cfg.discord_flavor,
+        providers = ?providers,
+        playing_poll_ms = cfg.intervals.playing_poll_ms,
+        paused_poll_ms = cfg.intervals.paused_poll_ms,
+        stopped_poll_ms = cfg.intervals.stopped_poll_ms,
+        buttons = cfg.enable_buttons,
+        assets = ?assets::referenced_keys(cfg),
+        log_level = %cfg.log_level,
+        "presence-bridge started"
+    );
+}
+
+/// Keeps only the last 4 characters of the app id, e.g. `**************5678`.
+fn mask_app_id(app_id: &str) -> String {
+    let app_id = app_id.trim();
+    if app_id.is_empty() {
+        return "(unset)".to_string();
+    }
+    let len = app_id.chars().count();
+    let shown = if len > 4 { 4 } else { 0 };
+    app_id
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < len - shown { '*' } else { c })
+        .collect()
+}
+
+fn warn_fallback_once(warned: &mut bool, snapshot: &ProviderSnapshot) {
+    if *warned || !snapshot.is_fallback() {
+        return;
+    }
+    *warned = true;
+    warn!(
+        provider = snapshot.provider_name,
+        "no media provider is available; check provider_priority in your config"
+    );
+}
+
+fn build_idle_gate(cfg: &AppConfig) -> Option<IdleGate> {
+    if !cfg.idle.enabled {
+        return None;
+    }
+    match system_idle_source() {
+        Some(source) => Some(IdleGate::new(
+            source,
+            Duration::from_millis(cfg.idle.threshold_ms),
+        )),
+        None => {
+            warn!("idle detection is not supported on this platform");
+            None
+        }
+    }
+}
+
+fn build_focus_gate(cfg: &AppConfig) -> Option<FocusGate> {
+    if !cfg.focus.enabled {
+        return None;
+    }
+    match system_foreground_source() {
+        Some(source) => Some(FocusGate::new(source, &cfg.focus.apps)),
+        None => {
+            warn!("focus detection is not available here; presence stays cleared");
+            Some(FocusGate::unsupported(&cfg.focus.apps))
+        }
+    }
+}
+
+fn spawn_config_watcher(path: PathBuf, poll_ms: u64, tx: mpsc::Sender<()>) {
+    tokio::spawn(async move {
+        let mut known_mtime = file_mtime(&path);
+        let sleep = Duration::from_millis(poll_ms.max(2_000));
+        loop {
+            tokio::time::sleep(sleep).await;
+            let current = file_mtime(&path);
+            if current.is_some() && current != known_mtime {
+                known_mtime = current;
+                if notify_reload(&tx).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+fn forward_reload_signals(mut signals: mpsc::Receiver<()>, tx: mpsc::Sender<()>) {
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            if notify_reload(&tx).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// A full channel already holds a pending reload, so extra signals are dropped.
+fn notify_reload(tx: &mpsc::Sender<()>) -> Result<(), mpsc::error::TrySendError<()>> {
+    match tx.try_send(()) {
+        Err(mpsc::error::TrySendError::Full(())) => Ok(()),
+        other => other,
+    }
+}
+
+/// Discards reload signals queued behind the one being handled.
+fn drain_pending(rx: &mut mpsc::Receiver<()>) -> usize {
+    let mut drained = 0;
+    while rx.try_recv().is_ok() {
+        drained += 1;
+    }
+    drained
+}
+
+fn file_mtime(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        drain_pending, mask_app_id, notify_reload, warn_fallback_once, FanOutSink, PresenceBridge,
+        PresenceSink,
+    };
+    use crate::idle::IdleGate;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use presence_bridge_core::{AppConfig, PlaybackState, SourceApp, Track, TrackLinks};
+    use presence_bridge_discord_rpc::ReconnectLimitReached;
+    use presence_bridge_engine::EngineAction;
+    use presence_bridge_providers::idle::IdleSource;
+    use presence_bridge_providers::{
+        NowPlayingProvider, ProviderCapabilities, ProviderChain, ProviderError, ProviderSnapshot,
+    };
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Clone, Default)]
+    struct CapturedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn warns_once_about_fallback_provider() {
+        let log = CapturedLog::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut warned = false;
+            for _ in 0..3 {
+                warn_fallback_once(&mut warned, &ProviderSnapshot::stopped("null"));
+            }
+            warn_fallback_once(&mut warned, &ProviderSnapshot::stopped("mpris"));
+        });
+
+        let output = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.matches("no media provider is available").count(), 1);
+    }
+
+    #[test]
+    fn masks_all_but_the_last_four_of_the_app_id() {
+        assert_eq!(mask_app_id("123456789012345678"), "**************5678");
+        assert_eq!(mask_app_id(" 12345 "), "*2345");
+        assert_eq!(mask_app_id("1234"), "****");
+        assert_eq!(mask_app_id(""), "(unset)");
+    }
+
+    #[tokio::test]
+    async fn bursts_of_reload_signals_coalesce() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(4);
+        for _ in 0..10 {
+            notify_reload(&tx).expect("receiver alive");
+        }
+
+        let mut reloads = 0;
+        while let Ok(Some(())) = tokio::time::timeout(Duration::from_millis(20), rx.recv()).await {
+            drain_pending(&mut rx);
+            reloads += 1;
+        }
+        assert_eq!(reloads, 1);
+    }
+
+    struct CountingProvider(u32);
+
+    #[async_trait]
+    impl NowPlayingProvider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn source(&self) -> SourceApp {
+            SourceApp::Unknown
+        }
+
+        async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+            self.0 += 1;
+            let mut snapshot = ProviderSnapshot::stopped(self.name());
+            snapshot.raw_state = Some(format!("poll-{}", self.0));
+            Ok(snapshot)
+        }
+    }
+
+    struct PlayingProvider;
+
+    #[async_trait]
+    impl NowPlayingProvider for PlayingProvider {
+        fn name(&self) -> &'static str {
+            "playing"
+        }
+
+        fn source(&self) -> SourceApp {
+            SourceApp::Unknown
+        }
+
+        async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+            Ok(ProviderSnapshot {
+                provider_name: self.name(),
+                state: PlaybackState::Playing,
+                track: Some(Track {
+                    id: "1".to_string(),
+                    title: "Title".to_string(),
+                    artist: "Artist".to_string(),
+                    album: None,
+                    track_number: None,
+                    year: None,
+                    duration_ms: Some(180_000),
+                    position_ms: Some(1_000),
+                    is_playing: true,
+                    source: SourceApp::Unknown,
+                    player: None,
+                    links: TrackLinks::default(),
+                    is_podcast: false,
+                    updated_at: SystemTime::now(),
+                }),
+                raw_state: None,
+                last_error: None,
+                capabilities: ProviderCapabilities::default(),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<EngineAction>>>);
+
+    #[async_trait]
+    impl PresenceSink for RecordingSink {
+        async fn apply(&mut self, action: EngineAction) -> Result<()> {
+            self.0.lock().unwrap().push(action);
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    #[async_trait]
+    impl PresenceSink for FailingSink {
+        async fn apply(&mut self, _action: EngineAction) -> Result<()> {
+            Err(anyhow::anyhow!("discord unreachable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn fan_out_reaches_every_sink_despite_failures() {
+        let first = RecordingSink::default();
+        let second = RecordingSink::default();
+        let mut bridge = PresenceBridge::builder()
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(FanOutSink::new(vec![
+                Box::new(first.clone()),
+                Box::new(FailingSink),
+                Box::new(second.clone()),
+            ]))
+            .build()
+            .expect("bridge");
+
+        let _ = bridge.step().await;
+
+        for recorded in [&first, &second] {
+            match recorded.0.lock().unwrap().as_slice() {
+                [EngineAction::Send(state)] => assert_eq!(state.details, "Artist — Title"),
+                other => panic!("unexpected actions: {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn builder_runs_one_iteration_with_fakes() {
+        let sink = RecordingSink::default();
+        let mut bridge = PresenceBridge::builder()
+            .config(AppConfig::default())
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(sink.clone())
+            .build()
+            .expect("bridge");
+
+        let next = bridge.step().await;
+        assert_eq!(next, Duration::from_millis(1_000));
+        let actions = sink.0.lock().unwrap();
+        match actions.as_slice() {
+            [EngineAction::Send(state)] => assert_eq!(state.details, "Artist — Title"),
+            other => panic!("unexpected actions: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn override_file_takes_over_until_removed() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-override-{}.json",
+            std::process::id()
+        ));
+        let sink = RecordingSink::default();
+        let mut bridge = PresenceBridge::builder()
+            .config(AppConfig {
+                override_file: Some(path.clone()),
+                ..AppConfig::default()
+            })
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(sink.clone())
+            .build()
+            .expect("bridge");
+
+        std::fs::write(
+            &path,
+            r#"{"activity_type": 0, "name": "Streaming", "details": "Live coding",
+                "state": "Be right back", "start_timestamp": null, "is_playing": true,
+                "large_image": null, "large_text": null, "small_image": null,
+                "small_text": null, "buttons": [], "party_size": null, "party_max": null,
+                "instance": null}"#,
+        )
+        .expect("write override");
+        let _ = bridge.step().await;
+        let _ = bridge.step().await;
+
+        std::fs::remove_file(&path).expect("remove override");
+        let _ = bridge.step().await;
+
+        let actions = sink.0.lock().unwrap();
+        match actions.as_slice() {
+            [EngineAction::Send(takeover), EngineAction::None, EngineAction::Send(track)] => {
+                assert_eq!(takeover.details, "Live coding");
+                assert_eq!(track.details, "Artist — Title");
+            }
+            other => panic!("unexpected actions: {other:?}"),
+        }
+    }
+
+    struct AlwaysIdle;
+
+    #[async_trait]
+    impl IdleSource for AlwaysIdle {
+        async fn idle_time(&mut self) -> Result<Duration> {
+            Ok(Duration::MAX)
+        }
+    }
+
+    #[tokio::test]
+    async fn override_removed_while_idle_is_cleared() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-override-idle-{}.json",
+            std::process::id()
+        ));
+        let sink = RecordingSink::default();
+        let mut bridge = PresenceBridge::builder()
+            .config(AppConfig {
+                override_file: Some(path.clone()),
+                ..AppConfig::default()
+            })
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(sink.clone())
+            .build()
+            .expect("bridge");
+        bridge.idle_gate = Some(IdleGate::new(Box::new(AlwaysIdle), Duration::from_secs(60)));
+
+        std::fs::write(
+            &path,
+            r#"{"activity_type": 0, "name": "Streaming", "details": "Live coding",
+                "state": "", "start_timestamp": null, "is_playing": true,
+                "large_image": null, "large_text": null, "small_image": null,
+                "small_text": null, "buttons": [], "party_size": null, "party_max": null,
+                "instance": null}"#,
+        )
+        .expect("write override");
+        let _ = bridge.step().await;
+        std::fs::remove_file(&path).expect("remove override");
+        let _ = bridge.step().await;
+
+        let actions = sink.0.lock().unwrap();
+        assert!(
+            matches!(
+                actions.as_slice(),
+                [EngineAction::Send(_), EngineAction::Clear]
+            ),
+            "unexpected actions: {actions:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn unrelated_reload_keeps_the_provider_chain() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-reload-chain-{}.toml",
+            std::process::id()
+        ));
+        let write_config = |cfg: &AppConfig| {
+            std::fs::write(&path, toml::to_string(cfg).unwrap()).expect("write config");
+        };
+        let mut cfg = AppConfig::default();
+        write_config(&cfg);
+        let mut bridge = PresenceBridge::builder()
+            .config(cfg.clone())
+            .config_path(&path)
+            .dry_run(true)
+            .build()
+            .expect("bridge");
+        bridge.chain = ProviderChain::new(vec![Box::new(CountingProvider(0))]);
+
+        cfg.log_level = "debug".to_string();
+        write_config(&cfg);
+        assert!(bridge.reload());
+        let snapshot = bridge.chain.poll_best().await;
+        assert_eq!(snapshot.provider_name, "counting");
+        assert_eq!(snapshot.raw_state.as_deref(), Some("poll-1"));
+
+        cfg.provider_priority = vec!["mpd".to_string()];
+        write_config(&cfg);
+        assert!(bridge.reload());
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(bridge.chain.provider_names(), ["mpd"]);
+    }
+
+    #[tokio::test]
+    async fn stop_clears_presence_and_ends_run() {
+        let sink = RecordingSink::default();
+        let mut bridge = PresenceBridge::builder()
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(sink.clone())
+            .build()
+            .expect("bridge");
+        let mut rx = bridge.subscribe();
+        let stop = bridge.stop_handle();
+
+        let handle = tokio::spawn(async move { bridge.run().await });
+        rx.recv().await.expect("first snapshot");
+        stop.stop();
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .expect("run ends")
+            .expect("join")
+            .expect("run ok");
+
+        let actions = sink.0.lock().unwrap();
+        assert!(matches!(actions.last(), Some(EngineAction::Clear)));
+    }
+
+    #[tokio::test]
+    async fn giving_up_on_discord_ends_run() {
+        struct GivenUpSink;
+
+        #[async_trait]
+        impl PresenceSink for GivenUpSink {
+            async fn apply(&mut self, _action: EngineAction) -> Result<()> {
+                Err(anyhow::Error::new(ReconnectLimitReached { attempts: 3 })
+                    .context("discord rpc set_activity failed"))
+            }
+        }
+
+        let mut bridge = PresenceBridge::builder()
+            .provider_chain(ProviderChain::new(vec![Box::new(PlayingProvider)]))
+            .sink(GivenUpSink)
+            .build()
+            .expect("bridge");
+        let err = tokio::time::timeout(Duration::from_secs(2), bridge.run())
+            .await
+            .expect("run ends")
+            .unwrap_err();
+        assert!(err.chain().any(|cause| cause.is::<ReconnectLimitReached>()));
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_every_snapshot() {
+        let mut cfg = AppConfig::default();
+        cfg.intervals.stopped_poll_ms = 5;
+        let mut bridge = PresenceBridge::builder()
+            .config(cfg)
+            .provider_chain(ProviderChain::new(vec![Box::new(CountingProvider(0))]))
+            .dry_run(true)
+            .build()
+            .expect("bridge");
+        let mut rx = bridge.subscribe();
+
+        let handle = tokio::spawn(async move { bridge.run().await });
+        for i in 1..=3 {
+            let snapshot = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("snapshot in time")
+                .expect("channel open");
+            assert_eq!(snapshot.provider_name, "counting");
+            assert_eq!(snapshot.state, PlaybackState::Stopped);
+            assert_eq!(snapshot.raw_state, Some(format!("poll-{i}")));
+        }
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn injected_reload_signal_reloads_the_config() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-reload-signal-{}.toml",
+            std::process::id()
+        ));
+        let mut cfg = AppConfig::default();
+        cfg.intervals.stopped_poll_ms = 5;
+        std::fs::write(&path, toml::to_string(&cfg).unwrap()).expect("write config");
+        let (signal_tx, signal_rx) = tokio::sync::mpsc::channel(1);
+        let mut bridge = PresenceBridge::builder()
+            .config(cfg.clone())
+            .config_path(&path)
+            .provider_chain(ProviderChain::new(vec![Box::new(CountingProvider(0))]))
+            .dry_run(true)
+            .reload_signals(signal_rx)
+            .build()
+            .expect("bridge");
+        let mut rx = bridge.outputs.subscribe();
+
+        let handle = tokio::spawn(async move { bridge.run().await });
+        rx.recv().await.expect("first output");
+        cfg.idle_presence.enabled = true;
+        std::fs::write(&path, toml::to_string(&cfg).unwrap()).expect("write config");
+        signal_tx.send(()).await.expect("bridge listening");
+
+        let reloaded = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let out = rx.recv().await.expect("channel open");
+                if matches!(out.action, EngineAction::Send(_)) {
+                    break;
+                }
+            }
+        })
+        .await;
+        handle.abort();
+        let _ = std::fs::remove_file(&path);
+        assert!(reloaded.is_ok(), "signal did not reload the config");
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Context, Result};
+use presence_bridge_core::AppConfig;
+use presence_bridge_providers::{AUTO_PROVIDER, PROVIDER_NAMES};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "macos")]
+pub fn default_config_path() -> PathBuf {
+    let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    base.join("presence-bridge").join("config.toml")
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn default_config_path() -> PathBuf {
+    let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    base.join("presence-bridge").join("config.toml")
+}
+
+pub fn init_config(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create config directory {}", parent.display()))?;
+    }
+    std::fs::write(path, default_config_toml()?)
+        .with_context(|| format!("failed to write config file {}", path.display()))?;
+    Ok(())
+}
+
+/// The built-in defaults as TOML, as written by `config init`.
+pub fn default_config_toml() -> Result<String> {
+    Ok(toml::to_string_pretty(&AppConfig::default())?)
+}
+
+pub fn load_or_default(path: &Path) -> Result<AppConfig> {
+    load_with_overrides(path).map(|(cfg, _)| cfg)
+}
+
+/// A config field replaced by an environment variable.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct EnvOverride {
+    pub field: &'static str,
+    pub var: &'static str,
+}
+
+/// A config file that isn't valid TOML or doesn't fit the schema, located to the line
+/// where parsing stopped when the parser knows it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigParseError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to parse {}", self.path.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " at line {line}, column {column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigParseError {}
+
+fn parse_config(path: &Path, data: &str) -> Result<AppConfig, ConfigParseError> {
+    toml::from_str(data).map_err(|err| {
+        let (line, column) = match err.span() {
+            Some(span) => {
+                let before = &data[..span.start.min(data.len())];
+                let line_start = before.rfind('\n').map_or(0, |at| at + 1);
+                (
+                    Some(before.matches('\n').count() + 1),
+                    Some(before[line_start..].chars().count() + 1),
+                )
+            }
+            None => (None, None),
+        };
+        ConfigParseError {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message: err
+                .message()
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    })
+}
+
+pub fn load_with_overrides(path: &Path) -> Result<(AppConfig, Vec<EnvOverride>)> {
+    let mut cfg = if !path.exists() {
+        AppConfig::default()
+    } else {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        parse_config(path, &data)?
+    };
+    let overrides = apply_env_overrides(&mut cfg);
+    let inline_from_env = overrides.iter().any(|o| o.field == "discord_app_id");
+    resolve_discord_app_id(&mut cfg, inline_from_env)?;
+    Ok((cfg, overrides))
+}
+
+/// Resolves `discord_app_id` with precedence: env value, id file, Keychain, inline config.
+pub fn resolve_discord_app_id(cfg: &mut AppConfig, inline_from_env: bool) -> Result<()> {
+    if inline_from_env {
+        return Ok(());
+    }
+    if let Some(path) = &cfg.discord_app_id_file {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read discord app id file {}", path.display()))?;
+        let id = raw.trim();
+        if id.is_empty() {
+            return Err(anyhow!("discord app id file {} is empty", path.display()));
+        }
+        cfg.discord_app_id = id.to_string();
+        return Ok(());
+    }
+    if let Some(service) = &cfg.discord_app_id_keychain {
+        cfg.discord_app_id = keychain_lookup(service)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_lookup(service: &str) -> Result<String> {
+    let output = std::process::Command::new("security")
+        .args(["find-generic-password", "-s", service, "-w"])
+        .output()
+        .context("failed to run security for keychain lookup")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "keychain item {service} not found: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_lookup(_service: &str) -> Result<String> {
+    Err(anyhow!(
+        "discord_app_id_keychain is only supported on macOS"
+    ))
+}
+
+pub fn apply_env_overrides(cfg: &mut AppConfig) -> Vec<EnvOverride> {
+    let mut applied = Vec::new();
+    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_DISCORD_APP_ID") {
+        if !v.trim().is_empty() {
+            cfg.discord_app_id = v;
+            applied.push(EnvOverride {
+                field: "discord_app_id",
+                var: "PRESENCE_BRIDGE_DISCORD_APP_ID",
+            });
+        }
+    }
+    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_DISCORD_APP_ID_FILE") {
+        if !v.trim().is_empty() {
+            cfg.discord_app_id_file = Some(PathBuf::from(v));
+            applied.push(EnvOverride {
+                field: "discord_app_id_file",
+                var: "PRESENCE_BRIDGE_DISCORD_APP_ID_FILE",
+            });
+        }
+    }
+    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_LOG_LEVEL") {
+        if !v.trim().is_empty() {
+            cfg.log_level = v;
+            applied.push(EnvOverride {
+                field: "log_level",
+                var: "PRESENCE_BRIDGE_LOG_LEVEL",
+            });
+        }
+    }
+    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_ENABLE_BUTTONS") {
+        if let Ok(parsed) = v.parse::<bool>() {
+            cfg.enable_buttons = parsed;
+            applied.push(EnvOverride {
+                field: "enable_buttons",
+                var: "PRESENCE_BRIDGE_ENABLE_BUTTONS",
+            });
+        }
+    }
+    applied
+}
+
+/// Result of `config check`; an empty `problems` list means the file is usable.
+#[derive(Debug, Serialize)]
+pub struct ConfigReport {
+    pub path: PathBuf,
+    pub problems: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<ConfigParseError>,
+}
+
+/// Loads `path` the way the daemon would and validates it, without touching Discord or providers.
+pub fn check_config(path: &Path) -> ConfigReport {
+    let mut parse_error = None;
+    let problems = if !path.exists() {
+        vec![format!("config file {} not found", path.display())]
+    } else {
+        match load_or_default(path) {
+            Ok(cfg) => validate(&cfg),
+            Err(err) => {
+                parse_error = err.downcast_ref::<ConfigParseError>().cloned();
+                vec![format!("{err:#}")]
+            }
+        }
+    };
+    ConfigReport {
+        path: path.to_path_buf(),
+        problems,
+        parse_error,
+    }
+}
+
+/// Returns human-readable problems with a loaded config.
+pub fn validate(cfg: &AppConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if cfg.schema_version != 1 {
+        problems.push(format!(
+            "unsupported schema_version {} (expected 1)",
+            cfg.schema_version
+        ));
+    }
+
+    let app_id = cfg.discord_app_id.trim();
+    if app_id.is_empty() || app_id == AppConfig::default().discord_app_id {
+        problems.push("discord_app_id is not set".to_string());
+    } else if !app_id.chars().all(|c| c.is_ascii_digit()) {
+        problems.push(format!(
+            "discord_app_id '{app_id}' is not a numeric application id"
+        ));
+    }
+
+    for id in &cfg.secondary_discord_app_ids {
+        if id.trim().is_empty() || !id.trim().chars().all(|c| c.is_ascii_digit()) {
+            problems.push(format!(
+                "secondary_discord_app_ids entry '{id}' is not a numeric application id"
+            ));
+        }
+    }
+
+    if cfg.provider_priority != [AUTO_PROVIDER] {
+        for name in &cfg.provider_priority {
+            if !PROVIDER_NAMES.contains(&name.as_str()) {
+                problems.push(format!(
+                    "unknown provider '{name}' in provider_priority; valid options: {}",
+                    PROVIDER_NAMES.join(", ")
+                ));
+            }
+        }
+    }
+
+    for (field, value) in [
+        ("intervals.playing_poll_ms", cfg.intervals.playing_poll_ms),
+        ("intervals.paused_poll_ms", cfg.intervals.paused_poll_ms),
+        ("intervals.stopped_poll_ms", cfg.intervals.stopped_poll_ms),
+    ] {
+        if value == 0 {
+            problems.push(format!("{field} must be greater than 0"));
+        }
+    }
+
+    if let (Some(size), Some(max)) = (cfg.party_size, cfg.party_max) {
+        if size > max {
+            problems.push(format!("party_size {size} exceeds party_max {max}"));
+        }
+    }
+    if cfg.idle_presence.enabled && cfg.idle_presence.details.trim().is_empty() {
+        problems.push("idle_presence.details is empty".to_string());
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_config, default_config_toml, load_or_default, resolve_discord_app_id,
+        ConfigParseError,
+    };
+    use presence_bridge_core::AppConfig;
+
+    fn id_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("presence-bridge-{name}-{}.txt", std::process::id()));
+        std::fs::write(&path, contents).expect("write id file");
+        path
+    }
+
+    #[test]
+    fn reads_trimmed_id_from_file() {
+        let path = id_file("app-id", "  123456789\n");
+        let mut cfg = AppConfig {
+            discord_app_id_file: Some(path.clone()),
+            ..AppConfig::default()
+        };
+
+        resolve_discord_app_id(&mut cfg, false).expect("resolved");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(cfg.discord_app_id, "123456789");
+    }
+
+    #[test]
+    fn env_value_beats_file_and_file_beats_inline() {
+        let path = id_file("precedence", "from-file");
+        let mut from_env = AppConfig {
+            discord_app_id: "from-env".to_string(),
+            discord_app_id_file: Some(path.clone()),
+            ..AppConfig::default()
+        };
+        resolve_discord_app_id(&mut from_env, true).expect("resolved");
+        assert_eq!(from_env.discord_app_id, "from-env");
+
+        let mut inline = AppConfig {
+            discord_app_id: "inline".to_string(),
+            discord_app_id_file: Some(path.clone()),
+            ..AppConfig::default()
+        };
+        resolve_discord_app_id(&mut inline, false).expect("resolved");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(inline.discord_app_id, "from-file");
+    }
+
+    #[test]
+    fn missing_or_empty_file_is_an_error() {
+        let mut missing = AppConfig {
+            discord_app_id_file: Some("/nonexistent/presence-bridge-id".into()),
+            ..AppConfig::default()
+        };
+        assert!(resolve_discord_app_id(&mut missing, false).is_err());
+
+        let path = id_file("empty", " \n");
+        let mut empty = AppConfig {
+            discord_app_id_file: Some(path.clone()),
+            ..AppConfig::default()
+        };
+        let result = resolve_discord_app_id(&mut empty, false);
+        let _ = std::fs::remove_file(&path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn check_accepts_a_valid_config() {
+        let cfg = AppConfig {
+            discord_app_id: "123456789012345678".to_string(),
+            ..AppConfig::default()
+        };
+        let path = id_file("check-valid", &toml::to_string_pretty(&cfg).unwrap());
+        let report = check_config(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn check_reports_invalid_config() {
+        let cfg = AppConfig {
+            provider_priority: vec!["winamp".to_string()],
+            party_size: Some(5),
+            party_max: Some(2),
+            ..AppConfig::default()
+        };
+        let path = id_file("check-invalid", &toml::to_string_pretty(&cfg).unwrap());
+        let report = check_config(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(report.problems.len(), 3, "{:?}", report.problems);
+        assert!(report.problems[1].contains("winamp"));
+
+        let path = id_file("check-unparseable", "discord_app_id = [");
+        let report = check_config(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].contains("failed to parse"));
+    }
+
+    #[test]
+    fn broken_toml_reports_where_parsing_stopped() {
+        let path = id_file(
+            "check-broken",
+            "schema_version = 1\nenable_buttons = true\nlog_level = \"info\n",
+        );
+        let report = check_config(&path);
+        let err = load_or_default(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["parse_error"]["line"], 3);
+        let parse = report.parse_error.expect("parse error");
+        assert_eq!((parse.line, parse.column), (Some(3), Some(18)));
+        assert_eq!(parse.path, path);
+        assert!(report.problems[0].contains(" at line 3, column 18: "));
+        assert_eq!(
+            err.downcast_ref::<ConfigParseError>()
+                .map(|e| e.message.as_str()),
+            Some(parse.message.as_str())
+        );
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let defaults = default_config_toml().unwrap();
+        let path = id_file("default-config", &defaults);
+        let loaded = load_or_default(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(toml::to_string_pretty(&loaded.unwrap()).unwrap(), defaults);
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use presence_bridge_providers::focus::ForegroundSource;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Caps one foreground lookup, which spawns `xprop` or `osascript`, so a hung X server
+/// can't stall the poll loop.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusTransition {
+    Focused,
+    LostFocus,
+    StillUnfocused,
+    Refocused,
+}
+
+/// Lets presence through only while one of `apps` owns the focused window.
+pub struct FocusGate {
+    source: Box<dyn ForegroundSource>,
+    /// Lowercased substrings of the foreground app's identifier.
+    apps: Vec<String>,
+    focused: bool,
+    warned: bool,
+    timeout: Duration,
+}
+
+/// Stands in where the focused app can't be read, so the gate keeps presence cleared
+/// instead of letting it through.
+struct NoForeground;
+
+#[async_trait]
+impl ForegroundSource for NoForeground {
+    async fn foreground_app(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+impl FocusGate {
+    pub fn new(source: Box<dyn ForegroundSource>, apps: &[String]) -> Self {
+        Self {
+            source,
+            apps: lowercased(apps),
+            focused: true,
+            warned: false,
+            timeout: LOOKUP_TIMEOUT,
+        }
+    }
+
+    /// A gate that never sees a listed app focused, for platforms without a source.
+    pub fn unsupported(apps: &[String]) -> Self {
+        Self::new(Box::new(NoForeground), apps)
+    }
+
+    pub fn set_apps(&mut self, apps: &[String]) {
+        self.apps = lowercased(apps);
+    }
+
+    fn matches(&self, app: &str) -> bool {
+        let app = app.to_lowercase();
+        self.apps.iter().any(|wanted| app.contains(wanted.as_str()))
+    }
+
+    pub async fn check(&mut self) -> FocusTransition {
+        // Unknown focus counts as unfocused: this gate exists to keep presence private.
+        let lookup = tokio::time::timeout(self.timeout, self.source.foreground_app())
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("foreground app lookup timed out")));
+        let now_focused = match lookup {
+            Ok(Some(app)) => self.matches(&app),
+            Ok(None) => false,
+            Err(err) => {
+                if !std::mem::replace(&mut self.warned, true) {
+                    warn!(error=%err, "cannot tell which app is focused; holding presence back");
+                } else {
+                    debug!(error=%err, "foreground app lookup failed");
+                }
+                false
+            }
+        };
+        let transition = match (self.focused, now_focused) {
+            (true, true) => FocusTransition::Focused,
+            (true, false) => FocusTransition::LostFocus,
+            (false, false) => FocusTransition::StillUnfocused,
+            (false, true) => FocusTransition::Refocused,
+        };
+        self.focused = now_focused;
+        transition
+    }
+}
+
+fn lowercased(apps: &[String]) -> Vec<String> {
+    apps.iter()
+        .map(|app| app.trim().to_lowercase())
+        .filter(|app| !app.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FocusGate, FocusTransition};
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use presence_bridge_providers::focus::ForegroundSource;
+    use std::time::Duration;
+
+    struct MockForeground(Vec<Result<Option<&'static str>>>);
+
+    #[async_trait]
+    impl ForegroundSource for MockForeground {
+        async fn foreground_app(&mut self) -> Result<Option<String>> {
+            self.0.remove(0).map(|app| app.map(String::from))
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_only_while_a_listed_app_is_focused() {
+        let source = MockForeground(vec![
+            Ok(Some("com.spotify.client")),
+            Ok(Some("org.mozilla.firefox")),
+            Ok(None),
+            Err(anyhow!("xprop missing")),
+            Ok(Some("Spotify.exe")),
+            Ok(Some("com.apple.Music")),
+        ]);
+        let apps = ["spotify".to_string(), " Music ".to_string()];
+        let mut gate = FocusGate::new(Box::new(source), &apps);
+
+        let mut seen = Vec::new();
+        for _ in 0..6 {
+            seen.push(gate.check().await);
+        }
+        assert_eq!(
+            seen,
+            vec![
+                FocusTransition::Focused,
+                FocusTransition::LostFocus,
+                FocusTransition::StillUnfocused,
+                FocusTransition::StillUnfocused,
+                FocusTransition::Refocused,
+                FocusTransition::Focused,
+            ]
+        );
+    }
+
+    struct HungForeground;
+
+    #[async_trait]
+    impl ForegroundSource for HungForeground {
+        async fn foreground_app(&mut self) -> Result<Option<String>> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_or_hung_lookups_hold_presence_back() {
+        let apps = ["spotify".to_string()];
+        let mut unsupported = FocusGate::unsupported(&apps);
+        assert_eq!(unsupported.check().await, FocusTransition::LostFocus);
+        assert_eq!(unsupported.check().await, FocusTransition::StillUnfocused);
+
+        let mut hung = FocusGate::new(Box::new(HungForeground), &apps);
+        hung.timeout = Duration::from_millis(20);
+        assert_eq!(hung.check().await, FocusTransition::LostFocus);
+    }
+
+    #[tokio::test]
+    async fn empty_list_never_matches() {
+        let source = MockForeground(vec![Ok(Some("com.spotify.client"))]);
+        let mut gate = FocusGate::new(Box::new(source), &[]);
+        assert_eq!(gate.check().await, FocusTransition::LostFocus);
+    }
+}
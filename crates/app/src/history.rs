@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use presence_bridge_core::{SourceApp, Track};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: u64,
+    pub source: SourceApp,
+    pub artist: String,
+    pub title: String,
+}
+
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+    /// The last recorded track, so resuming it after a stop isn't listed twice.
+    last: Option<Track>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            last: None,
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn record(&mut self, track: &Track, now: SystemTime) {
+        if self.capacity == 0 || self.last.as_ref() == Some(track) {
+            return;
+        }
+        self.last = Some(track.clone());
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            at: now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            source: track.source,
+            artist: track.artist.clone(),
+            title: track.title.clone(),
+        });
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    /// Writes the entries through `tokio::fs`, so a slow disk doesn't stall the runtime.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let entries: Vec<&HistoryEntry> = self.entries().collect();
+        let data = serde_json::to_string_pretty(&entries)?;
+        tokio::fs::write(path, data)
+            .await
+            .with_context(|| format!("failed to write history file {}", path.display()))
+    }
+}
+
+pub fn history_path(cfg_path: &Path) -> PathBuf {
+    cfg_path.with_file_name("history.json")
+}
+
+pub fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&data).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, History};
+    use presence_bridge_core::{SourceApp, Track, TrackLinks};
+    use std::time::SystemTime;
+
+    fn track(title: &str) -> Track {
+        Track {
+            id: title.to_string(),
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            album: None,
+            track_number: None,
+            year: None,
+            duration_ms: None,
+            position_ms: None,
+            is_playing: true,
+            source: SourceApp::Unknown,
+            player: None,
+            links: TrackLinks::default(),
+            is_podcast: false,
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_entries() {
+        let mut history = History::new(2);
+        for title in ["a", "b", "c"] {
+            history.record(&track(title), SystemTime::now());
+        }
+
+        let titles: Vec<&str> = history.entries().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["b", "c"]);
+
+        history.set_capacity(1);
+        let titles: Vec<&str> = history.entries().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["c"]);
+    }
+
+    #[test]
+    fn skips_the_same_track_recorded_again() {
+        let mut history = History::new(5);
+        let first = track("a");
+        history.record(&first, SystemTime::now());
+        let resumed = Track {
+            position_ms: Some(30_000),
+            updated_at: SystemTime::now(),
+            ..first.clone()
+        };
+        history.record(&resumed, SystemTime::now());
+        history.record(&track("b"), SystemTime::now());
+        history.record(&first, SystemTime::now());
+
+        let titles: Vec<&str> = history.entries().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["a", "b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn saved_history_loads_back() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-history-{}.json",
+            std::process::id()
+        ));
+        let mut history = History::new(2);
+        history.record(&track("a"), SystemTime::now());
+        history.save(&path).await.expect("save");
+
+        let loaded = load(&path).expect("load");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].title, "a");
+    }
+}
@@ -0,0 +1,89 @@
+use presence_bridge_providers::idle::IdleSource;
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleTransition {
+    Active,
+    BecameIdle,
+    StillIdle,
+    Resumed,
+}
+
+pub struct IdleGate {
+    source: Box<dyn IdleSource>,
+    threshold: Duration,
+    idle: bool,
+}
+
+impl IdleGate {
+    pub fn new(source: Box<dyn IdleSource>, threshold: Duration) -> Self {
+        Self {
+            source,
+            threshold,
+            idle: false,
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+    }
+
+    pub async fn check(&mut self) -> IdleTransition {
+        let idle_for = match self.source.idle_time().await {
+            Ok(d) => d,
+            Err(err) => {
+                debug!(error=%err, "idle detection failed; treating user as active");
+                Duration::ZERO
+            }
+        };
+        let now_idle = idle_for >= self.threshold;
+        let transition = match (self.idle, now_idle) {
+            (false, false) => IdleTransition::Active,
+            (false, true) => IdleTransition::BecameIdle,
+            (true, true) => IdleTransition::StillIdle,
+            (true, false) => IdleTransition::Resumed,
+        };
+        self.idle = now_idle;
+        transition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdleGate, IdleTransition};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use presence_bridge_providers::idle::IdleSource;
+    use std::time::Duration;
+
+    struct MockIdle(Vec<u64>);
+
+    #[async_trait]
+    impl IdleSource for MockIdle {
+        async fn idle_time(&mut self) -> Result<Duration> {
+            Ok(Duration::from_secs(self.0.remove(0)))
+        }
+    }
+
+    #[tokio::test]
+    async fn gates_on_threshold_transitions() {
+        let source = MockIdle(vec![10, 400, 500, 0, 5]);
+        let mut gate = IdleGate::new(Box::new(source), Duration::from_secs(300));
+
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            seen.push(gate.check().await);
+        }
+        assert_eq!(
+            seen,
+            vec![
+                IdleTransition::Active,
+                IdleTransition::BecameIdle,
+                IdleTransition::StillIdle,
+                IdleTransition::Resumed,
+                IdleTransition::Active,
+            ]
+        );
+    }
+}
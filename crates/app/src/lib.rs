@@ -0,0 +1,12 @@
+pub mod assets;
+pub mod bridge;
+pub mod config_file;
+mod focus;
+pub mod history;
+mod idle;
+mod notify;
+mod presence_override;
+mod rate_limit;
+pub mod runtime;
+pub mod service;
+pub mod top;
@@ -1,13 +1,20 @@
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use presence_bridge_core::AppConfig;
-use presence_bridge_discord_rpc::DiscordRpcClient;
-use presence_bridge_engine::{EngineAction, EngineConfig, EventEngine};
+use anyhow::{bail, Result};
+use clap::{ArgAction, Parser, Subcommand};
+use presence_bridge::assets;
+use presence_bridge::config_file::{
+    check_config, default_config_path, default_config_toml, init_config, load_or_default,
+    load_with_overrides, ConfigParseError,
+};
+use presence_bridge::history;
+use presence_bridge::runtime::{run, run_once, test_presence};
+use presence_bridge::service;
+use presence_bridge_core::{AppConfig, PlaybackState};
 use presence_bridge_providers::build_provider_chain;
+use serde::Serialize;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, SystemTime};
 use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use tracing::info;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -20,29 +27,90 @@ struct Cli {
 
     #[arg(long)]
     config: Option<PathBuf>,
+
+    /// Raise the log level (-v debug, -vv trace), overriding config and env.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Run,
-    Doctor,
+    Run {
+        /// Poll once, send or clear presence, then exit.
+        #[arg(long)]
+        once: bool,
+        /// Log presence updates instead of sending them to Discord.
+        #[arg(long)]
+        dry_run: bool,
+        /// Use only this provider, ignoring provider_priority (env: PRESENCE_BRIDGE_PROVIDER).
+        #[arg(long)]
+        provider: Option<String>,
+    },
+    Doctor {
+        /// Print a machine-readable report.
+        #[arg(long)]
+        json: bool,
+        /// Ask the Discord API which art assets the application has (needs the check-assets
+        /// feature; sends PRESENCE_BRIDGE_DISCORD_BOT_TOKEN when set).
+        #[arg(long)]
+        check_assets: bool,
+    },
     Status,
+    /// Send a fixed test presence for a few seconds, then clear it.
+    TestPresence {
+        /// Log the presence instead of sending it to Discord.
+        #[arg(long)]
+        dry_run: bool,
+        /// How long to show the test presence, in seconds.
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+    /// Show recently detected tracks.
+    History,
+    /// Run the daemon with a live terminal view of the track, provider and Discord state.
+    #[cfg(feature = "tui")]
+    Top {
+        /// Log presence updates instead of sending them to Discord.
+        #[arg(long)]
+        dry_run: bool,
+    },
     Config {
         #[command(subcommand)]
         action: ConfigAction,
     },
+    /// Run at login as a systemd user service (Linux) or launchd agent (macOS).
+    InstallService,
+    /// Stop and remove the service created by install-service.
+    UninstallService,
 }
 
 #[derive(Subcommand, Debug)]
 enum ConfigAction {
     Init,
+    /// Print the built-in default configuration without writing anything.
+    Default,
+    /// Print the effective configuration after defaults and env overrides.
+    Show {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Validate the config file and exit nonzero if it has problems.
+    Check {
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let cmd = cli.command.unwrap_or(Commands::Run);
+    let cmd = cli.command.unwrap_or(Commands::Run {
+        once: false,
+        dry_run: false,
+        provider: None,
+    });
     let cfg_path = cli.config.unwrap_or_else(default_config_path);
+    let verbose = cli.verbose;
 
     match cmd {
         Commands::Config {
@@ -52,133 +120,258 @@ async fn main() -> Result<()> {
             println!("Initialized config at {}", cfg_path.display());
             Ok(())
         }
-        Commands::Doctor => {
+        Commands::Config {
+            action: ConfigAction::Default,
+        } => {
+            print!("{}", default_config_toml()?);
+            Ok(())
+        }
+        Commands::Config {
+            action: ConfigAction::Show { json },
+        } => show_config(&cfg_path, json),
+        Commands::Config {
+            action: ConfigAction::Check { json },
+        } => config_check(&cfg_path, json),
+        Commands::Doctor { json, check_assets } => {
             let cfg = load_or_default(&cfg_path)?;
-            init_logging(&cfg.log_level);
-            doctor(&cfg).await
+            init_logging(&cfg.log_level, verbose);
+            doctor(&cfg, json, check_assets).await
         }
         Commands::Status => {
             let cfg = load_or_default(&cfg_path)?;
-            init_logging(&cfg.log_level);
+            init_logging(&cfg.log_level, verbose);
             status(&cfg).await
         }
-        Commands::Run => {
+        Commands::TestPresence { dry_run, seconds } => {
             let cfg = load_or_default(&cfg_path)?;
-            init_logging(&cfg.log_level);
-            run(cfg, cfg_path).await
+            init_logging(&cfg.log_level, verbose);
+            println!("Sending self-test presence for {seconds}s; check your Discord profile");
+            test_presence(&cfg, dry_run, Duration::from_secs(seconds)).await?;
+            println!("Self test OK: presence sent and cleared");
+            Ok(())
+        }
+        Commands::History => show_history(&history::history_path(&cfg_path)),
+        // No logging: log lines would draw over the terminal UI.
+        #[cfg(feature = "tui")]
+        Commands::Top { dry_run } => {
+            let cfg = load_or_default(&cfg_path)?;
+            presence_bridge::top::run_top(cfg, cfg_path, dry_run, reload_on_hangup()).await
+        }
+        Commands::InstallService => {
+            let path = service::install(&cfg_path)?;
+            println!("Installed service at {}", path.display());
+            Ok(())
+        }
+        Commands::UninstallService => {
+            let path = service::uninstall()?;
+            println!("Removed service at {}", path.display());
+            Ok(())
+        }
+        Commands::Run {
+            once,
+            dry_run,
+            provider,
+        } => {
+            let cfg = load_or_default(&cfg_path)?;
+            init_logging(&cfg.log_level, verbose);
+            let provider = provider.or_else(|| std::env::var("PRESENCE_BRIDGE_PROVIDER").ok());
+            if once {
+                run_once(&cfg, dry_run, provider.as_deref()).await
+            } else {
+                let reloads = reload_on_hangup();
+                run(cfg, cfg_path, dry_run, provider, reloads, shutdown_signal()).await
+            }
         }
     }
 }
 
-async fn run(mut cfg: AppConfig, cfg_path: PathBuf) -> Result<()> {
-    let mut chain = build_provider_chain(&cfg.provider_priority);
-    let mut engine = EventEngine::new(EngineConfig::from_app_config(&cfg));
-    let mut discord = DiscordRpcClient::new(cfg.discord_app_id.clone());
-
-    info!(providers = ?chain.provider_names(), "presence-bridge started");
-
-    let (reload_tx, mut reload_rx) = mpsc::channel::<()>(4);
-    spawn_reload_watchers(
-        cfg_path.clone(),
-        cfg.intervals.file_watch_poll_ms,
-        reload_tx,
-    )
-    .await?;
-
-    let mut next_poll_in = Duration::from_secs(0);
-
-    loop {
-        tokio::select! {
-            _ = tokio::time::sleep(next_poll_in) => {
-                let snapshot = chain.poll_best().await;
-                let out = engine.tick(snapshot, Instant::now(), SystemTime::now());
-                next_poll_in = out.next_poll_in;
-
-                match out.action {
-                    EngineAction::Send(state) => {
-                        if let Err(err) = discord.set_activity(&state).await {
-                            warn!(error=%err, "discord rpc set_activity failed; will retry with backoff");
-                        }
-                    }
-                    EngineAction::Clear => {
-                        if let Err(err) = discord.clear_activity().await {
-                            warn!(error=%err, "discord rpc clear_activity failed; will retry with backoff");
-                        }
-                    }
-                    EngineAction::None => {}
-                }
-            }
-            msg = reload_rx.recv() => {
-                if msg.is_some() {
-                    match load_or_default(&cfg_path) {
-                        Ok(new_cfg) => {
-                            cfg = new_cfg;
-                            engine.update_config(EngineConfig::from_app_config(&cfg));
-                            discord.update_client_id(cfg.discord_app_id.clone());
-                            chain = build_provider_chain(&cfg.provider_priority);
-                            info!("configuration reloaded");
-                            next_poll_in = Duration::from_secs(0);
-                        }
-                        Err(err) => {
-                            error!(error=%err, "failed to reload config");
-                        }
-                    }
+/// Turns SIGHUP into a config reload; elsewhere the watch on the config file is enough.
+fn reload_on_hangup() -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut hangup) = signal(SignalKind::hangup()) {
+            while hangup.recv().await.is_some() {
+                // A full channel already holds a pending reload.
+                if let Err(mpsc::error::TrySendError::Closed(())) = tx.try_send(()) {
+                    break;
                 }
             }
-            _ = tokio::signal::ctrl_c() => {
-                info!("received ctrl-c; shutting down");
-                break;
+        }
+    });
+    #[cfg(not(unix))]
+    drop(tx);
+    rx
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM from systemd or launchd stopping the service, so
+/// either clears the presence before exiting.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
             }
+            Err(_) => std::future::pending::<()>().await,
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("received ctrl-c"),
+        _ = terminate => info!("received SIGTERM"),
     }
+}
 
-    Ok(())
+#[derive(Debug, Serialize)]
+struct DoctorReport {
+    discord_reachable: bool,
+    provider_checked: &'static str,
+    provider_state: PlaybackState,
+    /// The provider's own state literal, e.g. MPRIS `PlaybackStatus`.
+    provider_raw_state: Option<String>,
+    now_playing: Option<NowPlaying>,
+    provider_error: Option<String>,
+    /// Art asset keys the config refers to; they must exist on the Discord application.
+    asset_keys: Vec<String>,
+    /// Referenced keys the Discord API doesn't list; only set with `--check-assets`.
+    missing_assets: Option<Vec<String>>,
+    platform_notes: Vec<String>,
 }
 
-async fn doctor(cfg: &AppConfig) -> Result<()> {
-    println!("== presence-bridge doctor ==");
+#[derive(Debug, Serialize)]
+struct NowPlaying {
+    artist: String,
+    title: String,
+}
 
-    let discord_ok = discord_running().await;
+async fn doctor(cfg: &AppConfig, json: bool, check_assets: bool) -> Result<()> {
+    let report = doctor_report(cfg, check_assets).await;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("== presence-bridge doctor ==");
     println!(
         "Discord RPC local endpoint: {}",
-        if discord_ok {
+        if report.discord_reachable {
             "reachable"
         } else {
             "not reachable"
         }
     );
-
-    let mut chain = build_provider_chain(&cfg.provider_priority);
-    let snapshot = chain.poll_best().await;
-    println!("Provider checked: {}", snapshot.provider_name);
-    println!("Provider state: {:?}", snapshot.state);
-
-    if let Some(track) = snapshot.track {
-        println!("Now playing: {} - {}", track.artist, track.title);
-    } else {
-        println!("No active media session");
+    println!("Provider checked: {}", report.provider_checked);
+    println!("Provider state: {:?}", report.provider_state);
+    if let Some(raw) = &report.provider_raw_state {
+        println!("Provider raw state: {raw}");
     }
-
-    if let Some(err) = snapshot.last_error {
+    match &report.now_playing {
+        Some(np) => println!("Now playing: {} - {}", np.artist, np.title),
+        None => println!("No active media session"),
+    }
+    if let Some(err) = &report.provider_error {
         println!("Provider error: {err}");
     }
+    if !report.asset_keys.is_empty() {
+        println!("Asset keys referenced: {}", report.asset_keys.join(", "));
+    }
+    match report.missing_assets.as_deref() {
+        Some([]) => println!("All referenced assets exist on the Discord application"),
+        Some(missing) => println!(
+            "Missing from the Discord application: {}",
+            missing.join(", ")
+        ),
+        None => {}
+    }
+    for note in &report.platform_notes {
+        println!("{note}");
+    }
 
-    #[cfg(target_os = "macos")]
-    {
-        println!(
-            "macOS automation: verify System Settings > Privacy & Security > Automation allows Terminal (or your shell) to control Music"
+    Ok(())
+}
+
+async fn doctor_report(cfg: &AppConfig, check_assets: bool) -> DoctorReport {
+    let discord_reachable = discord_running().await;
+
+    let mut chain = build_provider_chain(cfg);
+    let snapshot = chain.poll_best().await;
+
+    let mut platform_notes = Vec::new();
+    if snapshot.is_fallback() {
+        platform_notes.push(format!(
+            "No media provider is available for provider_priority {:?}; valid entries: auto, apple_music (macOS), windows (Windows), mpris (Linux), mpd, file",
+            cfg.provider_priority
+        ));
+    }
+    let asset_keys = assets::referenced_keys(cfg);
+    let mut missing_assets = None;
+    if check_assets {
+        let app_id = cfg.discord_app_id.clone();
+        let token = std::env::var("PRESENCE_BRIDGE_DISCORD_BOT_TOKEN").ok();
+        let uploaded = tokio::task::spawn_blocking(move || {
+            assets::fetch_uploaded_keys(&app_id, token.as_deref())
+        })
+        .await;
+        match uploaded {
+            Ok(Ok(uploaded)) => {
+                missing_assets = Some(
+                    asset_keys
+                        .iter()
+                        .filter(|key| !uploaded.contains(key))
+                        .cloned()
+                        .collect(),
+                )
+            }
+            Ok(Err(err)) => platform_notes.push(format!("Asset check failed: {err:#}")),
+            Err(err) => platform_notes.push(format!("Asset check failed: {err}")),
+        }
+    } else if !asset_keys.is_empty() {
+        platform_notes.push(
+            "Asset keys must be uploaded under Rich Presence > Art Assets in the Discord Developer Portal, or Discord shows no image; run doctor --check-assets to verify"
+                .to_string(),
         );
     }
+    #[cfg(target_os = "macos")]
+    platform_notes.push(
+        "macOS automation: verify System Settings > Privacy & Security > Automation allows Terminal (or your shell) to control Music"
+            .to_string(),
+    );
 
-    Ok(())
+    DoctorReport {
+        discord_reachable,
+        provider_checked: snapshot.provider_name,
+        provider_state: snapshot.state,
+        provider_raw_state: snapshot.raw_state,
+        now_playing: snapshot.track.map(|t| NowPlaying {
+            artist: t.artist,
+            title: t.title,
+        }),
+        provider_error: snapshot.last_error.map(|err| err.to_string()),
+        asset_keys,
+        missing_assets,
+        platform_notes,
+    }
 }
 
 async fn status(cfg: &AppConfig) -> Result<()> {
-    let mut chain = build_provider_chain(&cfg.provider_priority);
+    let mut chain = build_provider_chain(cfg);
     let snapshot = chain.poll_best().await;
 
     println!("provider: {}", snapshot.provider_name);
     println!("state: {:?}", snapshot.state);
+    if let Some(raw) = &snapshot.raw_state {
+        println!("raw state: {raw}");
+    }
     if let Some(track) = snapshot.track {
         println!("track: {} - {}", track.artist, track.title);
         if let Some(album) = track.album {
@@ -195,44 +388,93 @@ async fn status(cfg: &AppConfig) -> Result<()> {
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-fn default_config_path() -> PathBuf {
-    let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-    base.join("presence-bridge").join("config.toml")
+fn show_config(path: &Path, json: bool) -> Result<()> {
+    let (cfg, overrides) = match load_with_overrides(path) {
+        Ok(loaded) => loaded,
+        Err(err) if json => {
+            let out = serde_json::json!({
+                "error": format!("{err:#}"),
+                "parse_error": err.downcast_ref::<ConfigParseError>(),
+            });
+            println!("{}", serde_json::to_string_pretty(&out)?);
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    if json {
+        let out = serde_json::json!({
+            "config": cfg,
+            "env_overrides": overrides,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    if path.exists() {
+        println!("# source: {}", path.display());
+    } else {
+        println!("# source: built-in defaults ({} not found)", path.display());
+    }
+    for o in &overrides {
+        println!("# {} overridden by {}", o.field, o.var);
+    }
+    print!("{}", toml::to_string_pretty(&cfg)?);
+    Ok(())
 }
 
-#[cfg(not(target_os = "macos"))]
-fn default_config_path() -> PathBuf {
-    let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-    base.join("presence-bridge").join("config.toml")
+fn config_check(path: &Path, json: bool) -> Result<()> {
+    let report = check_config(path);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.problems.is_empty() {
+        println!("{}: OK", path.display());
+    } else {
+        println!("{}:", path.display());
+        for problem in &report.problems {
+            println!("  - {problem}");
+        }
+    }
+    if !report.problems.is_empty() {
+        bail!("config has {} problem(s)", report.problems.len());
+    }
+    Ok(())
 }
 
-fn init_config(path: &Path) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)
-            .with_context(|| format!("failed to create config directory {}", parent.display()))?;
+fn show_history(path: &Path) -> Result<()> {
+    let entries = history::load(path)?;
+    if entries.is_empty() {
+        println!("No tracks recorded yet");
+        return Ok(());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    for entry in entries.iter().rev() {
+        println!(
+            "{:>8} ago  [{:?}] {} - {}",
+            format_age(now.saturating_sub(entry.at)),
+            entry.source,
+            entry.artist,
+            entry.title
+        );
     }
-    let cfg = AppConfig::default();
-    let toml = toml::to_string_pretty(&cfg)?;
-    std::fs::write(path, toml)
-        .with_context(|| format!("failed to write config file {}", path.display()))?;
     Ok(())
 }
 
-fn load_or_default(path: &Path) -> Result<AppConfig> {
-    let mut cfg = if !path.exists() {
-        AppConfig::default()
-    } else {
-        let data = std::fs::read_to_string(path)
-            .with_context(|| format!("failed to read {}", path.display()))?;
-        toml::from_str(&data).with_context(|| format!("failed to parse {}", path.display()))?
-    };
-    apply_env_overrides(&mut cfg);
-    Ok(cfg)
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3_599 => format!("{}m", secs / 60),
+        3_600..=86_399 => format!("{}h", secs / 3_600),
+        _ => format!("{}d", secs / 86_400),
+    }
 }
 
-fn init_logging(log_level: &str) {
-    let filter = tracing_subscriber::EnvFilter::try_new(log_level)
+fn init_logging(log_level: &str, verbose: u8) {
+    let level = effective_log_level(log_level, verbose);
+    let filter = tracing_subscriber::EnvFilter::try_new(level)
         .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
 
     let _ = tracing_subscriber::fmt()
@@ -240,41 +482,18 @@ fn init_logging(log_level: &str) {
         .with_target(true)
         .with_thread_ids(true)
         .try_init();
-}
-
-async fn spawn_reload_watchers(path: PathBuf, poll_ms: u64, tx: mpsc::Sender<()>) -> Result<()> {
-    let tx_poll = tx.clone();
-    tokio::spawn(async move {
-        let mut known_mtime = file_mtime(&path);
-        let sleep = Duration::from_millis(poll_ms.max(2_000));
-        loop {
-            tokio::time::sleep(sleep).await;
-            let current = file_mtime(&path);
-            if current.is_some() && current != known_mtime {
-                known_mtime = current;
-                let _ = tx_poll.send(()).await;
-            }
-        }
-    });
 
-    #[cfg(unix)]
-    {
-        use tokio::signal::unix::{signal, SignalKind};
-        let tx_hup = tx.clone();
-        tokio::spawn(async move {
-            if let Ok(mut sig) = signal(SignalKind::hangup()) {
-                while sig.recv().await.is_some() {
-                    let _ = tx_hup.send(()).await;
-                }
-            }
-        });
+    if verbose > 0 {
+        tracing::info!(log_level = level, "log level raised by -v");
     }
-
-    Ok(())
 }
 
-fn file_mtime(path: &Path) -> Option<std::time::SystemTime> {
-    std::fs::metadata(path).ok()?.modified().ok()
+fn effective_log_level(configured: &str, verbose: u8) -> &str {
+    match verbose {
+        0 => configured,
+        1 if configured != "trace" => "debug",
+        _ => "trace",
+    }
 }
 
 async fn discord_running() -> bool {
@@ -320,20 +539,17 @@ fn discord_ipc_exists(slot: u8) -> bool {
     candidates.into_iter().any(|p| p.exists())
 }
 
-fn apply_env_overrides(cfg: &mut AppConfig) {
-    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_DISCORD_APP_ID") {
-        if !v.trim().is_empty() {
-            cfg.discord_app_id = v;
-        }
-    }
-    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_LOG_LEVEL") {
-        if !v.trim().is_empty() {
-            cfg.log_level = v;
-        }
-    }
-    if let Ok(v) = std::env::var("PRESENCE_BRIDGE_ENABLE_BUTTONS") {
-        if let Ok(parsed) = v.parse::<bool>() {
-            cfg.enable_buttons = parsed;
-        }
+#[cfg(test)]
+mod tests {
+    use super::effective_log_level;
+
+    #[test]
+    fn verbose_flag_raises_log_level() {
+        assert_eq!(effective_log_level("info", 0), "info");
+        assert_eq!(effective_log_level("warn", 1), "debug");
+        assert_eq!(effective_log_level("presence_bridge=info", 1), "debug");
+        assert_eq!(effective_log_level("trace", 1), "trace");
+        assert_eq!(effective_log_level("info", 2), "trace");
+        assert_eq!(effective_log_level("info", 5), "trace");
     }
 }
@@ -0,0 +1,87 @@
+use presence_bridge_core::Track;
+#[cfg(not(feature = "notify"))]
+use std::sync::Once;
+#[cfg(feature = "notify")]
+use tracing::debug;
+#[cfg(not(feature = "notify"))]
+use tracing::warn;
+
+/// Text of the desktop notification shown when the track changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackNotification {
+    pub summary: String,
+    pub body: String,
+}
+
+pub fn track_notification(track: &Track) -> TrackNotification {
+    let body = [Some(track.artist.as_str()), track.album.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" — ");
+    TrackNotification {
+        summary: track.title.clone(),
+        body,
+    }
+}
+
+/// Shows `notification` off the async runtime; failures are only logged. `notify-rust`
+/// talks to the notification daemon on Linux, Notification Center on macOS and shows a
+/// toast on Windows.
+#[cfg(feature = "notify")]
+pub fn show(notification: TrackNotification) {
+    tokio::task::spawn_blocking(move || {
+        let shown = notify_rust::Notification::new()
+            .appname("presence-bridge")
+            .summary(&notification.summary)
+            .body(&notification.body)
+            .show();
+        if let Err(err) = shown {
+            debug!(error = %err, "failed to show track notification");
+        }
+    });
+}
+
+/// Builds without the `notify` feature can't show anything; says so once.
+#[cfg(not(feature = "notify"))]
+pub fn show(_notification: TrackNotification) {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        warn!("notify_on_track_change needs a build with the notify feature; ignoring it");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::track_notification;
+    use presence_bridge_core::{SourceApp, Track, TrackLinks};
+    use std::time::SystemTime;
+
+    #[test]
+    fn builds_payload_from_track() {
+        let mut track = Track {
+            id: "1".to_string(),
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            album: Some("Album".to_string()),
+            track_number: None,
+            year: None,
+            duration_ms: None,
+            position_ms: None,
+            is_playing: true,
+            source: SourceApp::Unknown,
+            player: None,
+            links: TrackLinks::default(),
+            is_podcast: false,
+            updated_at: SystemTime::now(),
+        };
+        let n = track_notification(&track);
+        assert_eq!(n.summary, "Title");
+        assert_eq!(n.body, "Artist — Album");
+
+        track.artist.clear();
+        track.album = None;
+        assert_eq!(track_notification(&track).body, "");
+    }
+}
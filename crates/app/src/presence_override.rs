@@ -0,0 +1,153 @@
+use presence_bridge_engine::PresenceState;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub enum OverrideChange {
+    Unchanged,
+    /// The file appeared or changed; send this presence verbatim.
+    Set(Box<PresenceState>),
+    /// The file was removed or emptied; normal presence resumes.
+    Removed,
+}
+
+/// A manual takeover: while the file exists and is non-empty, its JSON presence replaces
+/// whatever the providers report. Only re-read when its mtime or size changes.
+pub struct PresenceOverride {
+    path: PathBuf,
+    seen: Option<(SystemTime, u64)>,
+    /// The presence the file holds, once it parsed.
+    state: Option<Box<PresenceState>>,
+    /// When `state` was last handed to Discord; `None` makes a resend due.
+    sent_at: Option<Instant>,
+}
+
+impl PresenceOverride {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            seen: None,
+            state: None,
+            sent_at: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// The active override when it should go out again: after a failed send, or once
+    /// `keepalive` has passed so a Discord that restarted meanwhile shows it again.
+    pub fn resend_due(&mut self, now: Instant, keepalive: Duration) -> Option<Box<PresenceState>> {
+        let state = self.state.as_ref()?;
+        if self
+            .sent_at
+            .is_some_and(|at| now.duration_since(at) < keepalive)
+        {
+            return None;
+        }
+        self.sent_at = Some(now);
+        Some(state.clone())
+    }
+
+    /// Makes the override due again because the last send didn't reach Discord.
+    pub fn send_failed(&mut self) {
+        self.sent_at = None;
+    }
+
+    pub fn check(&mut self) -> OverrideChange {
+        let stamp = std::fs::metadata(&self.path)
+            .ok()
+            .filter(|meta| meta.len() > 0)
+            .and_then(|meta| Some((meta.modified().ok()?, meta.len())));
+        let Some(stamp) = stamp else {
+            self.seen = None;
+            if self.state.take().is_some() {
+                info!(path = %self.path.display(), "presence override removed");
+                return OverrideChange::Removed;
+            }
+            return OverrideChange::Unchanged;
+        };
+        if self.seen == Some(stamp) {
+            return OverrideChange::Unchanged;
+        }
+        self.seen = Some(stamp);
+
+        let parsed = std::fs::read_to_string(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|raw| Ok(serde_json::from_str::<PresenceState>(&raw)?));
+        match parsed {
+            Ok(state) => {
+                if self.state.is_none() {
+                    info!(path = %self.path.display(), "presence override active");
+                }
+                let state = Box::new(state);
+                self.state = Some(state.clone());
+                self.sent_at = Some(Instant::now());
+                OverrideChange::Set(state)
+            }
+            // A half-written or invalid file keeps whatever is shown until it is fixed.
+            Err(err) => {
+                warn!(path = %self.path.display(), error = %err, "ignoring invalid presence override");
+                OverrideChange::Unchanged
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OverrideChange, PresenceOverride};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ignores_missing_empty_and_invalid_files() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-override-invalid-{}.json",
+            std::process::id()
+        ));
+        let mut takeover = PresenceOverride::new(path.clone());
+        assert!(matches!(takeover.check(), OverrideChange::Unchanged));
+
+        std::fs::write(&path, "").unwrap();
+        assert!(matches!(takeover.check(), OverrideChange::Unchanged));
+
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(matches!(takeover.check(), OverrideChange::Unchanged));
+        assert!(!takeover.is_active());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn resends_after_a_failed_send_and_each_keepalive() {
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-override-resend-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"{"activity_type": 0, "name": "Streaming", "details": "Live coding",
+                "state": "", "start_timestamp": null, "is_playing": true,
+                "large_image": null, "large_text": null, "small_image": null,
+                "small_text": null, "buttons": [], "party_size": null, "party_max": null,
+                "instance": null}"#,
+        )
+        .unwrap();
+        let mut takeover = PresenceOverride::new(path.clone());
+        let keepalive = Duration::from_secs(60);
+        assert!(matches!(takeover.check(), OverrideChange::Set(_)));
+        let _ = std::fs::remove_file(&path);
+
+        let now = Instant::now();
+        assert!(takeover.resend_due(now, keepalive).is_none());
+        takeover.send_failed();
+        assert!(takeover.resend_due(now, keepalive).is_some());
+        assert!(takeover.resend_due(now, keepalive).is_none());
+        let resent = takeover.resend_due(now + keepalive, keepalive);
+        assert_eq!(
+            resent.map(|state| state.details).as_deref(),
+            Some("Live coding")
+        );
+    }
+}
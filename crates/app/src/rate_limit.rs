@@ -0,0 +1,111 @@
+use presence_bridge_engine::EngineAction;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Token bucket capping how often presence updates reach the sink, independent of the engine.
+/// An update over budget is held back and replaced by newer ones until a token frees up.
+pub struct SendLimiter {
+    max_sends: u32,
+    window: Duration,
+    tokens: f64,
+    refilled_at: Option<Instant>,
+    pending: Option<EngineAction>,
+}
+
+impl SendLimiter {
+    /// `max_sends == 0` disables the limiter.
+    pub fn new(max_sends: u32, window: Duration) -> Self {
+        Self {
+            max_sends,
+            window,
+            tokens: max_sends as f64,
+            refilled_at: None,
+            pending: None,
+        }
+    }
+
+    pub fn configure(&mut self, max_sends: u32, window: Duration) {
+        self.max_sends = max_sends;
+        self.window = window;
+        self.tokens = self.tokens.min(max_sends as f64);
+    }
+
+    /// Returns the action to apply now; a throttled update is deferred to a later call.
+    pub fn admit(&mut self, action: EngineAction, now: Instant) -> EngineAction {
+        if self.max_sends == 0 || self.window.is_zero() {
+            return action;
+        }
+        self.refill(now);
+        let action = match action {
+            EngineAction::None => match self.pending.take() {
+                Some(pending) => pending,
+                None => return EngineAction::None,
+            },
+            action => action,
+        };
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.pending = None;
+            return action;
+        }
+        if self.pending.is_none() {
+            info!(
+                max_sends = self.max_sends,
+                window_ms = self.window.as_millis() as u64,
+                "presence send rate exceeded; deferring update"
+            );
+        }
+        self.pending = Some(action);
+        EngineAction::None
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(last) = self.refilled_at {
+            let rate = self.max_sends as f64 / self.window.as_secs_f64();
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(self.max_sends as f64);
+        }
+        self.refilled_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendLimiter;
+    use presence_bridge_engine::EngineAction;
+    use std::time::{Duration, Instant};
+
+    fn is_sent(action: &EngineAction) -> bool {
+        !matches!(action, EngineAction::None)
+    }
+
+    #[test]
+    fn caps_a_burst_and_delivers_the_latest_later() {
+        let mut limiter = SendLimiter::new(5, Duration::from_secs(20));
+        let start = Instant::now();
+
+        let sent = (0..20)
+            .map(|i| limiter.admit(EngineAction::Clear, start + Duration::from_millis(i)))
+            .filter(is_sent)
+            .count();
+        assert_eq!(sent, 5);
+
+        // Nothing new from the engine, but the deferred update goes out once a token refills.
+        assert!(!is_sent(
+            &limiter.admit(EngineAction::None, start + Duration::from_secs(1))
+        ));
+        assert!(is_sent(
+            &limiter.admit(EngineAction::None, start + Duration::from_secs(5))
+        ));
+        assert!(!is_sent(
+            &limiter.admit(EngineAction::None, start + Duration::from_secs(60))
+        ));
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let mut limiter = SendLimiter::new(0, Duration::from_secs(20));
+        let now = Instant::now();
+        assert!((0..50).all(|_| is_sent(&limiter.admit(EngineAction::Clear, now))));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::bridge::{build_chain, DiscordSink, PresenceBridge};
+use anyhow::{Context, Result};
+use presence_bridge_core::{AppConfig, AssetChoice};
+use presence_bridge_engine::{EngineAction, EngineConfig, EventEngine, PresenceState};
+use presence_bridge_providers::ProviderSnapshot;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// Runs the daemon loop until `shutdown` resolves; `provider` pins a single provider
+/// instead of `provider_priority`. Each message on `reloads` reloads the config file;
+/// the caller decides which signals send one.
+pub async fn run(
+    cfg: AppConfig,
+    cfg_path: PathBuf,
+    dry_run: bool,
+    provider: Option<String>,
+    reloads: mpsc::Receiver<()>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let mut builder = PresenceBridge::builder()
+        .config(cfg)
+        .config_path(cfg_path)
+        .dry_run(dry_run)
+        .reload_signals(reloads);
+    if let Some(name) = provider {
+        builder = builder.provider(name);
+    }
+    let mut bridge = builder.build()?;
+
+    let stop = bridge.stop_handle();
+    tokio::spawn(async move {
+        shutdown.await;
+        stop.stop();
+    });
+    bridge.run().await
+}
+
+/// Spawns the daemon loop and returns a receiver observing every provider snapshot.
+pub fn run_with_subscription(
+    cfg: AppConfig,
+    cfg_path: PathBuf,
+    dry_run: bool,
+) -> Result<(
+    JoinHandle<Result<()>>,
+    broadcast::Receiver<ProviderSnapshot>,
+)> {
+    let mut bridge = PresenceBridge::builder()
+        .config(cfg)
+        .config_path(cfg_path)
+        .dry_run(dry_run)
+        .build()?;
+    let rx = bridge.subscribe();
+    let handle = tokio::spawn(async move { bridge.run().await });
+    Ok((handle, rx))
+}
+
+pub async fn run_once(cfg: &AppConfig, dry_run: bool, provider: Option<&str>) -> Result<()> {
+    let mut chain = build_chain(cfg, provider)?;
+    let mut engine = EventEngine::new(EngineConfig::from_app_config(cfg));
+    let mut sink = DiscordSink::for_all_apps(cfg, dry_run);
+
+    let snapshot = chain.poll_best().await;
+    let out = engine.tick(snapshot, Instant::now(), SystemTime::now());
+    debug!(reason = ?out.reason, diff = ?out.diff, "engine decision");
+
+    // A fresh engine has never sent anything, so an empty poll clears explicitly.
+    let action = match out.action {
+        EngineAction::None => EngineAction::Clear,
+        action => action,
+    };
+    sink.apply(action).await
+}
+
+/// The fixed activity sent by `test-presence`, using the configured assets.
+pub fn self_test_presence(cfg: &AppConfig) -> PresenceState {
+    PresenceState {
+        activity_type: 2,
+        name: "Listening".to_string(),
+        details: "presence-bridge self test".to_string(),
+        state: "If you can see this, Discord is set up".to_string(),
+        start_timestamp: None,
+        is_playing: true,
+        large_image: cfg
+            .assets
+            .large_image
+            .as_ref()
+            .and_then(AssetChoice::first)
+            .map(str::to_string),
+        large_text: cfg.assets.large_text.clone(),
+        small_image: cfg.assets.small_play_image.clone(),
+        small_text: cfg.assets.small_play_text.clone(),
+        buttons: Vec::new(),
+        party_size: None,
+        party_max: None,
+        instance: None,
+    }
+}
+
+/// Sends [`self_test_presence`] to every configured app, holds it for `hold`, then clears.
+pub async fn test_presence(cfg: &AppConfig, dry_run: bool, hold: Duration) -> Result<()> {
+    let mut sink = DiscordSink::for_all_apps(cfg, dry_run);
+    sink.apply(EngineAction::Send(Box::new(self_test_presence(cfg))))
+        .await
+        .context("failed to send the self-test presence")?;
+    info!(hold_secs = hold.as_secs(), "self-test presence sent");
+    tokio::time::sleep(hold).await;
+    sink.apply(EngineAction::Clear)
+        .await
+        .context("failed to clear the self-test presence")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::self_test_presence;
+    use presence_bridge_core::{AppConfig, AssetChoice};
+
+    #[test]
+    fn self_test_presence_uses_configured_assets() {
+        let mut cfg = AppConfig::default();
+        cfg.assets.large_image = Some(AssetChoice::Single("custom_icon".to_string()));
+
+        let presence = self_test_presence(&cfg);
+        assert_eq!(presence.details, "presence-bridge self test");
+        assert_eq!(presence.large_image.as_deref(), Some("custom_icon"));
+        assert_eq!(presence.small_image.as_deref(), Some("play"));
+        assert!(presence.start_timestamp.is_none());
+        assert!(presence.buttons.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+const SERVICE_NAME: &str = "presence-bridge";
+const LAUNCHD_LABEL: &str = "com.presence-bridge.daemon";
+
+/// A systemd user unit running the daemon with `config`.
+pub fn systemd_unit(exe: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=presence-bridge (Now Playing -> Discord Rich Presence)\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart=\"{}\" --config \"{}\" run\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exe.display(),
+        config.display()
+    )
+}
+
+/// A launchd agent plist running the daemon with `config` at login.
+pub fn launchd_plist(exe: &Path, config: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LAUNCHD_LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--config</string>
+        <string>{}</string>
+        <string>run</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+</dict>
+</plist>
+"#,
+        xml_escape(&exe.display().to_string()),
+        xml_escape(&config.display().to_string())
+    )
+}
+
+fn xml_escape(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Where the service definition for this platform is written.
+pub fn service_file_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("could not determine the home directory")?;
+    if cfg!(target_os = "macos") {
+        Ok(home
+            .join("Library/LaunchAgents")
+            .join(format!("{LAUNCHD_LABEL}.plist")))
+    } else if cfg!(target_os = "linux") {
+        let base = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+        Ok(base
+            .join("systemd/user")
+            .join(format!("{SERVICE_NAME}.service")))
+    } else {
+        bail!("install-service supports systemd (Linux) and launchd (macOS) only")
+    }
+}
+
+/// Writes the service definition for the current executable and starts it where possible.
+pub fn install(config: &Path) -> Result<PathBuf> {
+    let path = service_file_path()?;
+    let exe = std::env::current_exe().context("failed to locate the presence-bridge binary")?;
+    let config = std::path::absolute(config)
+        .with_context(|| format!("failed to resolve {}", config.display()))?;
+    let contents = if cfg!(target_os = "macos") {
+        launchd_plist(&exe, &config)
+    } else {
+        systemd_unit(&exe, &config)
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    std::fs::write(&path, contents)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    if cfg!(target_os = "macos") {
+        best_effort("launchctl", &["load", "-w", &path.display().to_string()]);
+    } else {
+        best_effort("systemctl", &["--user", "daemon-reload"]);
+        best_effort(
+            "systemctl",
+            &[
+                "--user",
+                "enable",
+                "--now",
+                &format!("{SERVICE_NAME}.service"),
+            ],
+        );
+    }
+    Ok(path)
+}
+
+/// Stops the service and removes its definition; returns the removed path.
+pub fn uninstall() -> Result<PathBuf> {
+    let path = service_file_path()?;
+    if !path.exists() {
+        bail!("no service installed at {}", path.display());
+    }
+    if cfg!(target_os = "macos") {
+        best_effort("launchctl", &["unload", "-w", &path.display().to_string()]);
+    } else {
+        best_effort(
+            "systemctl",
+            &[
+                "--user",
+                "disable",
+                "--now",
+                &format!("{SERVICE_NAME}.service"),
+            ],
+        );
+    }
+    std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    if cfg!(target_os = "linux") {
+        best_effort("systemctl", &["--user", "daemon-reload"]);
+    }
+    Ok(path)
+}
+
+fn best_effort(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => debug!(program, ?args, %status, "service command failed"),
+        Err(err) => debug!(program, ?args, error = %err, "failed to run service command"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{launchd_plist, systemd_unit};
+    use std::path::Path;
+
+    #[test]
+    fn renders_systemd_unit() {
+        let unit = systemd_unit(
+            Path::new("/opt/presence bridge/presence-bridge"),
+            Path::new("/home/me/.config/presence-bridge/config.toml"),
+        );
+        assert!(unit.contains(
+            "ExecStart=\"/opt/presence bridge/presence-bridge\" --config \"/home/me/.config/presence-bridge/config.toml\" run\n"
+        ));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+
+    #[test]
+    fn renders_launchd_plist() {
+        let plist = launchd_plist(
+            Path::new("/usr/local/bin/presence-bridge"),
+            Path::new("/Users/me/R&D/config.toml"),
+        );
+        assert!(plist.contains("<string>com.presence-bridge.daemon</string>"));
+        assert!(plist.contains("<string>/usr/local/bin/presence-bridge</string>"));
+        assert!(plist.contains("<string>/Users/me/R&amp;D/config.toml</string>"));
+        assert!(plist.contains("<key>RunAtLoad</key>\n    <true/>"));
+    }
+}
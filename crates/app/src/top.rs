@@ -0,0 +1,313 @@
+use presence_bridge_core::{DiscordFlavor, PlaybackState, Track};
+use presence_bridge_discord_rpc::ConnectionEvent;
+use presence_bridge_engine::{EngineAction, EngineOutput};
+use presence_bridge_providers::ProviderSnapshot;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const LOG_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscordStatus {
+    Connecting,
+    Connected(Option<DiscordFlavor>),
+    Disconnected,
+    BackingOff { until: Instant },
+    DryRun,
+}
+
+impl DiscordStatus {
+    pub fn describe(&self, now: Instant) -> String {
+        match self {
+            Self::Connecting => "connecting".to_string(),
+            Self::Connected(Some(flavor)) => format!("connected ({flavor:?})"),
+            Self::Connected(None) => "connected".to_string(),
+            Self::Disconnected => "disconnected".to_string(),
+            Self::BackingOff { until } => format!(
+                "unreachable, retrying in {}s",
+                until.saturating_duration_since(now).as_secs()
+            ),
+            Self::DryRun => "dry run (not sending)".to_string(),
+        }
+    }
+}
+
+/// Everything `presence-bridge top` shows, folded from the bridge's broadcast channels.
+pub struct TopState {
+    started_at: Instant,
+    pub provider: &'static str,
+    pub state: PlaybackState,
+    pub track: Option<Track>,
+    pub provider_error: Option<String>,
+    pub discord: DiscordStatus,
+    /// Most recent first.
+    pub log: VecDeque<String>,
+}
+
+impl TopState {
+    pub fn new(dry_run: bool, started_at: Instant) -> Self {
+        Self {
+            started_at,
+            provider: "none",
+            state: PlaybackState::Stopped,
+            track: None,
+            provider_error: None,
+            discord: if dry_run {
+                DiscordStatus::DryRun
+            } else {
+                DiscordStatus::Connecting
+            },
+            log: VecDeque::new(),
+        }
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: ProviderSnapshot) {
+        self.provider = snapshot.provider_name;
+        self.state = snapshot.state;
+        self.track = snapshot.track;
+        self.provider_error = snapshot.last_error.map(|err| err.to_string());
+    }
+
+    /// Logs decisions that changed something; quiet polls are left out.
+    pub fn apply_output(&mut self, output: &EngineOutput, at: Instant) {
+        let line = match &output.action {
+            EngineAction::Send(presence) => format!(
+                "sent \"{}\" / \"{}\" ({:?})",
+                presence.details, presence.state, output.reason
+            ),
+            EngineAction::Clear => format!("cleared ({:?})", output.reason),
+            EngineAction::None => return,
+        };
+        self.push_log(at, line);
+    }
+
+    pub fn apply_connection(&mut self, event: ConnectionEvent, at: Instant) {
+        if self.discord == DiscordStatus::DryRun {
+            return;
+        }
+        let line = match event {
+            ConnectionEvent::Connected { flavor } => {
+                self.discord = DiscordStatus::Connected(flavor);
+                "connected to discord"
+            }
+            ConnectionEvent::Disconnected => {
+                self.discord = DiscordStatus::Disconnected;
+                "lost connection to discord"
+            }
+            ConnectionEvent::BackingOff { until } => {
+                self.discord = DiscordStatus::BackingOff { until };
+                return;
+            }
+        };
+        self.push_log(at, line.to_string());
+    }
+
+    fn push_log(&mut self, at: Instant, line: String) {
+        let elapsed = at.saturating_duration_since(self.started_at);
+        self.log
+            .push_front(format!("[{}] {line}", format_elapsed(elapsed)));
+        self.log.truncate(LOG_CAPACITY);
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(feature = "tui")]
+pub use ui::run_top;
+
+#[cfg(feature = "tui")]
+mod ui {
+    use super::TopState;
+    use crate::bridge::PresenceBridge;
+    use anyhow::Result;
+    use presence_bridge_core::AppConfig;
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, List, Paragraph};
+    use ratatui::Frame;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+    use tokio::sync::mpsc;
+
+    const REDRAW_EVERY: Duration = Duration::from_millis(250);
+
+    /// Runs the daemon in-process and shows its state until `q`, Esc or Ctrl-C. Each
+    /// message on `reloads` reloads the config file.
+    pub async fn run_top(
+        cfg: AppConfig,
+        cfg_path: PathBuf,
+        dry_run: bool,
+        reloads: mpsc::Receiver<()>,
+    ) -> Result<()> {
+        let mut bridge = PresenceBridge::builder()
+            .config(cfg)
+            .config_path(cfg_path)
+            .dry_run(dry_run)
+            .reload_signals(reloads)
+            .build()?;
+        let mut snapshots = bridge.subscribe();
+        let mut outputs = bridge.subscribe_outputs();
+        let mut connection = bridge.subscribe_connection();
+        let stop = bridge.stop_handle();
+        let mut daemon = tokio::spawn(async move { bridge.run().await });
+        let mut daemon_done = false;
+
+        let mut state = TopState::new(dry_run, Instant::now());
+        let mut terminal = ratatui::init();
+        let mut redraw = tokio::time::interval(REDRAW_EVERY);
+        let result = loop {
+            tokio::select! {
+                // The bridge only ends on its own when it fails, e.g. after giving up on
+                // Discord; leave with its error rather than showing a stale screen.
+                joined = &mut daemon => {
+                    daemon_done = true;
+                    break match joined {
+                        Ok(done) => done,
+                        Err(err) => Err(err.into()),
+                    };
+                }
+                Ok(snapshot) = snapshots.recv() => state.apply_snapshot(snapshot),
+                Ok(output) = outputs.recv() => state.apply_output(&output, Instant::now()),
+                Ok(event) = connection.recv() => state.apply_connection(event, Instant::now()),
+                _ = redraw.tick() => {
+                    if let Err(err) = terminal.draw(|frame| draw(frame, &state)) {
+                        break Err(err.into());
+                    }
+                    match quit_requested() {
+                        Ok(false) => {}
+                        Ok(true) => break Ok(()),
+                        Err(err) => break Err(err),
+                    }
+                }
+            }
+        };
+        ratatui::restore();
+
+        if !daemon_done {
+            stop.stop();
+            daemon.await??;
+        }
+        result
+    }
+
+    fn quit_requested() -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press
+                    && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+                {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn draw(frame: &mut Frame, state: &TopState) {
+        let [status_area, log_area] =
+            Layout::vertical([Constraint::Length(8), Constraint::Min(3)]).areas(frame.area());
+
+        let track = match &state.track {
+            Some(track) => format!("{} — {}", track.artist, track.title),
+            None => "nothing playing".to_string(),
+        };
+        let album = state
+            .track
+            .as_ref()
+            .and_then(|track| track.album.clone())
+            .unwrap_or_default();
+        let mut lines = vec![
+            Line::from(format!("Track:    {track}")),
+            Line::from(format!("Album:    {album}")),
+            Line::from(format!("State:    {:?}", state.state)),
+            Line::from(format!("Provider: {}", state.provider)),
+            Line::from(format!(
+                "Discord:  {}",
+                state.discord.describe(Instant::now())
+            )),
+        ];
+        if let Some(err) = &state.provider_error {
+            lines.push(Line::from(format!("Error:    {err}")));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" presence-bridge — q to quit ")),
+            status_area,
+        );
+        frame.render_widget(
+            List::new(state.log.iter().map(String::as_str))
+                .block(Block::bordered().title(" Recent actions ")),
+            log_area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiscordStatus, TopState, LOG_CAPACITY};
+    use presence_bridge_core::PlaybackState;
+    use presence_bridge_discord_rpc::ConnectionEvent;
+    use presence_bridge_engine::{DecisionReason, DiffKind, EngineAction, EngineOutput};
+    use presence_bridge_providers::{ProviderError, ProviderSnapshot};
+    use std::time::{Duration, Instant};
+
+    fn output(action: EngineAction) -> EngineOutput {
+        EngineOutput {
+            action,
+            next_poll_in: Duration::from_secs(1),
+            diff: DiffKind::Nothing,
+            reason: DecisionReason::Cleared,
+        }
+    }
+
+    #[test]
+    fn folds_channel_events_into_the_view() {
+        let start = Instant::now();
+        let mut state = TopState::new(false, start);
+        assert_eq!(state.discord, DiscordStatus::Connecting);
+
+        state.apply_snapshot(ProviderSnapshot::with_error(
+            "mpd",
+            ProviderError::Timeout("status".to_string()),
+        ));
+        assert_eq!(state.provider, "mpd");
+        assert_eq!(state.state, PlaybackState::Stopped);
+        assert_eq!(state.provider_error.as_deref(), Some("timed out: status"));
+
+        state.apply_connection(
+            ConnectionEvent::Connected { flavor: None },
+            start + Duration::from_secs(1),
+        );
+        state.apply_output(&output(EngineAction::None), start + Duration::from_secs(2));
+        state.apply_output(
+            &output(EngineAction::Clear),
+            start + Duration::from_secs(65),
+        );
+        assert_eq!(state.discord, DiscordStatus::Connected(None));
+        assert_eq!(
+            Vec::from(state.log.clone()),
+            [
+                "[00:01:05] cleared (Cleared)",
+                "[00:00:01] connected to discord"
+            ]
+        );
+
+        for _ in 0..LOG_CAPACITY + 10 {
+            state.apply_output(&output(EngineAction::Clear), start);
+        }
+        assert_eq!(state.log.len(), LOG_CAPACITY);
+    }
+
+    #[test]
+    fn dry_run_ignores_connection_events() {
+        let mut state = TopState::new(true, Instant::now());
+        state.apply_connection(ConnectionEvent::Disconnected, Instant::now());
+        assert_eq!(state.discord, DiscordStatus::DryRun);
+        assert!(state.log.is_empty());
+    }
+}
@@ -1,17 +1,80 @@
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 
 fn default_schema_version() -> u32 {
     1
 }
 
+fn default_show_timestamp() -> bool {
+    true
+}
+
+fn default_discord_ws_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_hash_start_timestamp() -> bool {
+    true
+}
+
+fn default_history_size() -> usize {
+    20
+}
+
+fn default_provider_error_grace_ms() -> u64 {
+    10_000
+}
+
+fn default_small_play_text() -> Option<String> {
+    Some("Playing".to_string())
+}
+
+fn default_small_pause_text() -> Option<String> {
+    Some("Paused".to_string())
+}
+
+fn default_locale() -> String {
+    "en".to_string()
+}
+
+fn default_presence_keepalive_ms() -> u64 {
+    300_000
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigIntervals {
     pub playing_poll_ms: u64,
     pub paused_poll_ms: u64,
     pub stopped_poll_ms: u64,
     pub presence_min_update_ms: u64,
+    #[serde(default = "default_presence_keepalive_ms")]
+    pub presence_keepalive_ms: u64,
     pub debounce_ms: u64,
     pub file_watch_poll_ms: u64,
+    #[serde(default = "default_provider_error_grace_ms")]
+    pub provider_error_grace_ms: u64,
+    /// How long a stop must last before presence is cleared; 0 clears immediately.
+    #[serde(default)]
+    pub clear_grace_ms: u64,
+    /// A provider's stopped read this soon after it reported playback is treated as a
+    /// glitch and its last snapshot reused; 0 trusts every read.
+    #[serde(default)]
+    pub provider_stop_hysteresis_ms: u64,
+    /// Poll this fast right after a change, backing off to the state's interval while
+    /// nothing changes; 0 keeps fixed intervals.
+    #[serde(default)]
+    pub adaptive_poll_min_ms: u64,
+    /// Pings Discord after this long without an update so a dead connection is noticed
+    /// before the next one; 0 disables the check.
+    #[serde(default)]
+    pub health_check_ms: u64,
+    /// Minimum poll interval while a given provider is the active one, for providers that
+    /// are expensive to query (osascript, MPD over the network).
+    #[serde(default)]
+    pub provider_poll_floor_ms: BTreeMap<String, u64>,
 }
 
 impl Default for ConfigIntervals {
@@ -21,27 +84,288 @@ impl Default for ConfigIntervals {
             paused_poll_ms: 7_000,
             stopped_poll_ms: 30_000,
             presence_min_update_ms: 15_000,
+            presence_keepalive_ms: default_presence_keepalive_ms(),
             debounce_ms: 500,
             file_watch_poll_ms: 10_000,
+            provider_error_grace_ms: default_provider_error_grace_ms(),
+            clear_grace_ms: 0,
+            provider_stop_hysteresis_ms: 0,
+            adaptive_poll_min_ms: 0,
+            health_check_ms: 0,
+            provider_poll_floor_ms: BTreeMap::new(),
+        }
+    }
+}
+
+/// An asset key, or several to rotate between per track.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AssetChoice {
+    Single(String),
+    Rotate(Vec<String>),
+}
+
+impl AssetChoice {
+    /// Picks a key deterministically from `seed`, so the same track keeps the same image.
+    pub fn pick(&self, seed: &str) -> Option<&str> {
+        match self {
+            AssetChoice::Single(key) => Some(key),
+            AssetChoice::Rotate(keys) if keys.is_empty() => None,
+            AssetChoice::Rotate(keys) => {
+                let mut hasher = DefaultHasher::new();
+                seed.hash(&mut hasher);
+                Some(&keys[(hasher.finish() % keys.len() as u64) as usize])
+            }
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        match self {
+            AssetChoice::Single(key) => std::slice::from_ref(key),
+            AssetChoice::Rotate(keys) => keys,
+        }
+    }
+
+    /// The key used when there is no track to pick by.
+    pub fn first(&self) -> Option<&str> {
+        match self {
+            AssetChoice::Single(key) => Some(key),
+            AssetChoice::Rotate(keys) => keys.first().map(String::as_str),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetsConfig {
-    pub large_image: Option<String>,
+    pub large_image: Option<AssetChoice>,
     pub large_text: Option<String>,
+    /// Used instead of `large_image` / `large_text` while paused, when set.
+    #[serde(default)]
+    pub large_image_paused: Option<String>,
+    #[serde(default)]
+    pub large_text_paused: Option<String>,
     pub small_play_image: Option<String>,
     pub small_pause_image: Option<String>,
+    #[serde(default = "default_small_play_text")]
+    pub small_play_text: Option<String>,
+    #[serde(default = "default_small_pause_text")]
+    pub small_pause_text: Option<String>,
 }
 
 impl Default for AssetsConfig {
     fn default() -> Self {
         Self {
-            large_image: Some("app_icon".to_string()),
+            large_image: Some(AssetChoice::Single("app_icon".to_string())),
             large_text: Some("presence-bridge".to_string()),
+            large_image_paused: None,
+            large_text_paused: None,
             small_play_image: Some("play".to_string()),
             small_pause_image: Some("pause".to_string()),
+            small_play_text: default_small_play_text(),
+            small_pause_text: default_small_pause_text(),
+        }
+    }
+}
+
+/// Which Discord desktop build to prefer when several are running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscordFlavor {
+    #[default]
+    Any,
+    Stable,
+    Ptb,
+    Canary,
+    Development,
+}
+
+/// Which local RPC transports the Discord client may use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscordTransport {
+    /// IPC first, then the local WebSocket.
+    #[default]
+    Auto,
+    Ipc,
+    Ws,
+}
+
+/// How `ProviderChain` picks a snapshot when several providers report media.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderSelection {
+    /// The first provider in priority order that isn't stopped.
+    #[default]
+    FirstNonStopped,
+    /// Polls every provider and prefers one that is actually playing.
+    PreferPlaying,
+}
+
+/// Shortcut for arranging the track across the details and state lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceLayout {
+    /// `Artist — Title`, album below.
+    #[default]
+    ArtistTitle,
+    /// Title, artist below.
+    TitleArtist,
+    /// Title, album below.
+    TitleOnly,
+}
+
+/// What makes two polls the same track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackIdentity {
+    /// The provider's own id; a handover between providers counts as a track change.
+    #[default]
+    Id,
+    /// [`Track::fingerprint`](crate::Track::fingerprint), stable across providers.
+    Fingerprint,
+}
+
+/// What the presence's start timestamp counts from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampMode {
+    /// The start of the current track.
+    #[default]
+    PerTrack,
+    /// The first play of the listening session, kept across track changes until a stop.
+    Session,
+}
+
+/// How search links turn track metadata into a query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchQueryMode {
+    /// `"{artist} {title}"` exactly as reported.
+    #[default]
+    Raw,
+    /// Drops bracketed suffixes and featured artists for better search hits.
+    Clean,
+}
+
+/// The Discord activity verb shown above the presence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    #[default]
+    Listening,
+    Watching,
+}
+
+impl ActivityKind {
+    /// Discord's numeric activity type.
+    pub fn discord_type(self) -> u8 {
+        match self {
+            ActivityKind::Listening => 2,
+            ActivityKind::Watching => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MpdConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for MpdConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 6600,
+        }
+    }
+}
+
+fn default_osascript_attempts() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppleMusicConfig {
+    /// Tries per poll before a failing `osascript` call counts as an error.
+    #[serde(default = "default_osascript_attempts")]
+    pub osascript_attempts: u32,
+    /// JXA script to run instead of the one built into the binary.
+    #[serde(default)]
+    pub jxa_script_path: Option<PathBuf>,
+}
+
+impl Default for AppleMusicConfig {
+    fn default() -> Self {
+        Self {
+            osascript_attempts: default_osascript_attempts(),
+            jxa_script_path: None,
+        }
+    }
+}
+
+/// Source for the `file` provider: a JSON file or named pipe written by another program.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileProviderConfig {
+    pub path: Option<PathBuf>,
+}
+
+/// Caps presence updates sent to Discord; Discord documents 5 per 20 seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendLimitConfig {
+    /// 0 disables the limit.
+    pub max_sends: u32,
+    pub window_ms: u64,
+}
+
+impl Default for SendLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_sends: 5,
+            window_ms: 20_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    pub threshold_ms: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_ms: 300_000,
+        }
+    }
+}
+
+/// Sends presence only while one of `apps` owns the focused window; each entry is a
+/// case-insensitive substring of the bundle id (macOS), executable (Windows) or `WM_CLASS` (X11).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FocusConfig {
+    pub enabled: bool,
+    #[serde(default)]
+    pub apps: Vec<String>,
+}
+
+/// Placeholder activity shown while nothing is playing instead of clearing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdlePresenceConfig {
+    pub enabled: bool,
+    pub details: String,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+impl Default for IdlePresenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            details: "Not listening to anything".to_string(),
+            state: None,
         }
     }
 }
@@ -51,11 +375,122 @@ pub struct AppConfig {
     #[serde(default = "default_schema_version")]
     pub schema_version: u32,
     pub discord_app_id: String,
+    #[serde(default)]
+    pub discord_app_id_file: Option<PathBuf>,
+    #[serde(default)]
+    pub discord_app_id_keychain: Option<String>,
+    /// Additional Discord applications that mirror the same presence.
+    #[serde(default)]
+    pub secondary_discord_app_ids: Vec<String>,
+    #[serde(default)]
+    pub discord_flavor: DiscordFlavor,
+    #[serde(default)]
+    pub discord_transport: DiscordTransport,
+    /// Where the WebSocket transport looks for Discord, for a client in a VM or container.
+    #[serde(default = "default_discord_ws_host")]
+    pub discord_ws_host: String,
+    /// An HTTP proxy (`http://[user:pass@]host:port`) the WebSocket connects through with `CONNECT`.
+    #[serde(default)]
+    pub discord_ws_proxy: Option<String>,
+    /// Stops the daemon after this many failed connects to Discord in a row; 0 retries
+    /// forever.
+    #[serde(default)]
+    pub discord_max_reconnect_attempts: u32,
+    /// Empty or `["auto"]` picks the providers for the current OS.
+    #[serde(default)]
     pub provider_priority: Vec<String>,
+    #[serde(default)]
+    pub provider_selection: ProviderSelection,
     pub intervals: ConfigIntervals,
+    #[serde(default)]
+    pub send_limit: SendLimitConfig,
     pub enable_buttons: bool,
+    /// Prepended to each button label, e.g. an emoji; labels are cut to Discord's limit.
+    #[serde(default)]
+    pub button_prefix: Option<String>,
+    #[serde(default)]
+    pub search_query: SearchQueryMode,
+    #[serde(default = "default_show_timestamp")]
+    pub show_timestamp: bool,
+    #[serde(default)]
+    pub party_size: Option<u32>,
+    #[serde(default)]
+    pub party_max: Option<u32>,
+    #[serde(default)]
+    pub activity_instance: Option<bool>,
+    /// Tracks with a known duration below this are ignored; 0 disables the filter.
+    #[serde(default)]
+    pub min_track_duration_ms: u64,
+    /// A track ignored for being short is still shown once it has played this long;
+    /// 0 keeps ignoring it.
+    #[serde(default)]
+    pub min_visible_ms: u64,
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    #[serde(default = "default_locale")]
+    pub locale: String,
+    #[serde(default)]
+    pub presence_layout: PresenceLayout,
+    #[serde(default)]
+    pub track_identity: TrackIdentity,
+    #[serde(default)]
+    pub timestamp_mode: TimestampMode,
+    /// Names the activity after the source (e.g. "Apple Music") instead of "Listening".
+    #[serde(default)]
+    pub name_from_source: bool,
+    /// Sends the details line and timestamp only: no state line, images or buttons.
+    #[serde(default)]
+    pub compact: bool,
+    /// Appends the source to the small image's text, e.g. `Playing · Apple Music`.
+    #[serde(default)]
+    pub small_text_source: bool,
+    /// Shows a desktop notification with the artist and title on every track change; needs
+    /// a build with the `notify` feature.
+    #[serde(default)]
+    pub notify_on_track_change: bool,
+    /// Appends the frozen position to the paused state, e.g. `Paused · 1:23 / 3:45`.
+    #[serde(default)]
+    pub show_position_when_paused: bool,
+    /// When false, a presence differing only in its start timestamp counts as unchanged.
+    #[serde(default = "default_hash_start_timestamp")]
+    pub hash_start_timestamp: bool,
+    /// Overrides the playing state line; supports `{album}`, `{artist}`, `{title}`,
+    /// `{track_number}` and `{year}`.
+    #[serde(default)]
+    pub state_template: Option<String>,
+    /// Maps a case-insensitive substring of the player name (an MPRIS bus name or a
+    /// Windows app id) to an activity type, e.g. `{"vlc": "watching"}`.
+    #[serde(default)]
+    pub player_activity: BTreeMap<String, ActivityKind>,
+    /// MPRIS bus names to consider; empty allows every player. Entries are
+    /// case-insensitive substrings, or globs when they contain `*`.
+    #[serde(default)]
+    pub mpris_allow: Vec<String>,
+    /// MPRIS bus names never to use, matched like `mpris_allow`; wins over it.
+    #[serde(default)]
+    pub mpris_deny: Vec<String>,
+    /// Splits MPRIS titles like `"Artist - Title"` at this separator when the player sends
+    /// no artist.
+    #[serde(default)]
+    pub mpris_title_separator: Option<String>,
+    /// While this file exists and is non-empty, its JSON presence is sent verbatim
+    /// instead of what the providers report.
+    #[serde(default)]
+    pub override_file: Option<PathBuf>,
     pub log_level: String,
     pub assets: AssetsConfig,
+    #[serde(default)]
+    pub apple_music: AppleMusicConfig,
+    #[serde(default)]
+    pub mpd: MpdConfig,
+    #[serde(default)]
+    pub file: FileProviderConfig,
+    #[serde(default)]
+    pub idle: IdleConfig,
+    #[serde(default)]
+    pub idle_presence: IdlePresenceConfig,
+    #[serde(default)]
+    pub focus: FocusConfig,
 }
 
 impl Default for AppConfig {
@@ -63,15 +498,52 @@ impl Default for AppConfig {
         Self {
             schema_version: default_schema_version(),
             discord_app_id: "YOUR_DISCORD_APP_ID".to_string(),
-            provider_priority: vec![
-                "apple_music".to_string(),
-                "windows".to_string(),
-                "mpris".to_string(),
-            ],
+            discord_app_id_file: None,
+            discord_app_id_keychain: None,
+            secondary_discord_app_ids: Vec::new(),
+            discord_flavor: DiscordFlavor::Any,
+            discord_transport: DiscordTransport::Auto,
+            discord_ws_host: default_discord_ws_host(),
+            discord_ws_proxy: None,
+            discord_max_reconnect_attempts: 0,
+            provider_priority: vec!["auto".to_string()],
+            provider_selection: ProviderSelection::FirstNonStopped,
             intervals: ConfigIntervals::default(),
+            send_limit: SendLimitConfig::default(),
             enable_buttons: true,
+            button_prefix: None,
+            search_query: SearchQueryMode::Raw,
+            show_timestamp: default_show_timestamp(),
+            party_size: None,
+            party_max: None,
+            activity_instance: None,
+            min_track_duration_ms: 0,
+            min_visible_ms: 0,
+            history_size: default_history_size(),
+            locale: default_locale(),
+            presence_layout: PresenceLayout::ArtistTitle,
+            track_identity: TrackIdentity::Id,
+            timestamp_mode: TimestampMode::PerTrack,
+            name_from_source: false,
+            compact: false,
+            small_text_source: false,
+            notify_on_track_change: false,
+            show_position_when_paused: false,
+            hash_start_timestamp: true,
+            state_template: None,
+            player_activity: BTreeMap::new(),
+            mpris_allow: Vec::new(),
+            mpris_deny: Vec::new(),
+            mpris_title_separator: None,
+            override_file: None,
             log_level: "info".to_string(),
             assets: AssetsConfig::default(),
+            apple_music: AppleMusicConfig::default(),
+            mpd: MpdConfig::default(),
+            file: FileProviderConfig::default(),
+            idle: IdleConfig::default(),
+            idle_presence: IdlePresenceConfig::default(),
+            focus: FocusConfig::default(),
         }
     }
 }
@@ -1,6 +1,12 @@
 pub mod config;
 pub mod model;
+pub mod text;
 pub mod urls;
 
-pub use config::{AppConfig, AssetsConfig, ConfigIntervals};
-pub use model::{PlaybackState, SourceApp, Track, TrackLinks};
+pub use config::{
+    ActivityKind, AppConfig, AppleMusicConfig, AssetChoice, AssetsConfig, ConfigIntervals,
+    DiscordFlavor, DiscordTransport, FileProviderConfig, FocusConfig, IdleConfig,
+    IdlePresenceConfig, MpdConfig, PresenceLayout, ProviderSelection, SearchQueryMode,
+    SendLimitConfig, TimestampMode, TrackIdentity,
+};
+pub use model::{clamp_position, is_podcast_genre, PlaybackState, SourceApp, Track, TrackLinks};
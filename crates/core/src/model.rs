@@ -1,14 +1,31 @@
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use std::time::SystemTime;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum SourceApp {
     AppleMusicMac,
     WindowsMediaSession,
     Mpris,
+    Mpd,
+    File,
     Unknown,
 }
 
+impl SourceApp {
+    /// Human-facing name of the source, used as the activity name with `name_from_source`.
+    pub fn display_name(self) -> &'static str {
+        match self {
+            SourceApp::AppleMusicMac => "Apple Music",
+            SourceApp::WindowsMediaSession => "Windows Media",
+            SourceApp::Mpris => "Media Player",
+            SourceApp::Mpd => "MPD",
+            SourceApp::File => "Now Playing",
+            SourceApp::Unknown => "Music",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PlaybackState {
     Playing,
@@ -16,7 +33,7 @@ pub enum PlaybackState {
     Stopped,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 pub struct TrackLinks {
     pub apple_music: Option<String>,
     pub spotify_search: Option<String>,
@@ -28,10 +45,161 @@ pub struct Track {
     pub title: String,
     pub artist: String,
     pub album: Option<String>,
+    #[serde(default)]
+    pub track_number: Option<u32>,
+    #[serde(default)]
+    pub year: Option<u32>,
     pub duration_ms: Option<u64>,
     pub position_ms: Option<u64>,
     pub is_playing: bool,
     pub source: SourceApp,
+    /// Identifies the player within the source, e.g. the MPRIS bus name.
+    #[serde(default)]
+    pub player: Option<String>,
+    /// A podcast episode: `title` is the episode and `album` the show.
+    #[serde(default)]
+    pub is_podcast: bool,
     pub links: TrackLinks,
     pub updated_at: SystemTime,
 }
+
+// `updated_at` and `position_ms` differ on every read of the same playing track, so they
+// are left out of `==` and `Hash`. The destructuring is exhaustive so a new field can't be
+// missed by accident.
+impl PartialEq for Track {
+    fn eq(&self, other: &Self) -> bool {
+        let Track {
+            id,
+            title,
+            artist,
+            album,
+            track_number,
+            year,
+            duration_ms,
+            position_ms: _,
+            is_playing,
+            source,
+            player,
+            is_podcast,
+            links,
+            updated_at: _,
+        } = self;
+        *id == other.id
+            && *title == other.title
+            && *artist == other.artist
+            && *album == other.album
+            && *track_number == other.track_number
+            && *year == other.year
+            && *duration_ms == other.duration_ms
+            && *is_playing == other.is_playing
+            && *source == other.source
+            && *player == other.player
+            && *is_podcast == other.is_podcast
+            && *links == other.links
+    }
+}
+
+impl Eq for Track {}
+
+impl Hash for Track {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Track {
+            id,
+            title,
+            artist,
+            album,
+            track_number,
+            year,
+            duration_ms,
+            position_ms: _,
+            is_playing,
+            source,
+            player,
+            is_podcast,
+            links,
+            updated_at: _,
+        } = self;
+        id.hash(state);
+        title.hash(state);
+        artist.hash(state);
+        album.hash(state);
+        track_number.hash(state);
+        year.hash(state);
+        duration_ms.hash(state);
+        is_playing.hash(state);
+        source.hash(state);
+        player.hash(state);
+        is_podcast.hash(state);
+        links.hash(state);
+    }
+}
+
+impl Track {
+    /// Provider-independent identity: lowercased artist and title with whitespace collapsed,
+    /// so the same song reported by two providers (with different `id`s) matches.
+    pub fn fingerprint(&self) -> String {
+        let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!(
+            "{}\u{1f}{}",
+            normalize(&self.artist.to_lowercase()),
+            normalize(&self.title.to_lowercase())
+        )
+    }
+}
+
+/// Whether a genre tag marks the item as a podcast episode.
+pub fn is_podcast_genre(genre: &str) -> bool {
+    genre.to_lowercase().contains("podcast")
+}
+
+/// Caps a reported position at the track duration; some players overshoot near the end.
+pub fn clamp_position(position_ms: Option<u64>, duration_ms: Option<u64>) -> Option<u64> {
+    match (position_ms, duration_ms) {
+        (Some(pos), Some(dur)) if dur > 0 => Some(pos.min(dur)),
+        (pos, _) => pos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceApp, Track, TrackLinks};
+    use std::collections::HashSet;
+    use std::time::{Duration, SystemTime};
+
+    fn track() -> Track {
+        Track {
+            id: "t1".to_string(),
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: Some("Album".to_string()),
+            track_number: None,
+            year: None,
+            duration_ms: Some(180_000),
+            position_ms: Some(1_000),
+            is_playing: true,
+            source: SourceApp::Mpris,
+            player: None,
+            is_podcast: false,
+            links: TrackLinks::default(),
+            updated_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn equality_ignores_updated_at_and_position() {
+        let a = track();
+        let b = Track {
+            updated_at: a.updated_at + Duration::from_secs(5),
+            position_ms: a.position_ms.map(|p| p + 5_000),
+            ..a.clone()
+        };
+        assert_eq!(a, b);
+        assert_eq!(HashSet::from([a.clone(), b]).len(), 1);
+
+        let paused = Track {
+            is_playing: false,
+            ..a.clone()
+        };
+        assert_ne!(a, paused);
+    }
+}
@@ -0,0 +1,69 @@
+/// Strips control and zero-width characters and collapses whitespace runs.
+pub fn clean_metadata(raw: &str) -> String {
+    let filtered: String = raw
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control() && !is_zero_width(*c))
+        .collect();
+    filtered.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Reads the year from a date like `2013`, `2013-05-17` or `2013-05-17T00:00:00Z`.
+pub fn parse_year(raw: &str) -> Option<u32> {
+    let digits = raw.trim().get(..4)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().filter(|y| *y > 0)
+}
+
+/// Splits `"Artist - Title"` at the first `separator` into `(artist, title)`, or `None`
+/// when either side would be empty.
+pub fn split_artist_title(title: &str, separator: &str) -> Option<(String, String)> {
+    if separator.is_empty() {
+        return None;
+    }
+    let (artist, title) = title.split_once(separator)?;
+    let (artist, title) = (artist.trim(), title.trim());
+    (!artist.is_empty() && !title.is_empty()).then(|| (artist.to_string(), title.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clean_metadata, split_artist_title};
+
+    #[test]
+    fn cleans_dirty_metadata() {
+        assert_eq!(clean_metadata("Song \n"), "Song");
+        assert_eq!(clean_metadata("  Daft\t\tPunk  "), "Daft Punk");
+        assert_eq!(clean_metadata("Get\u{200B} Lucky\u{FEFF}"), "Get Lucky");
+        assert_eq!(clean_metadata("Bad\u{0007}Bell"), "BadBell");
+        assert_eq!(clean_metadata("Café — Été"), "Café — Été");
+    }
+
+    #[test]
+    fn cleaned_id_is_stable_across_polls() {
+        let first = format!("{}:{}", clean_metadata("Artist "), clean_metadata("Song\n"));
+        let second = format!("{}:{}", clean_metadata(" Artist"), clean_metadata("Song"));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn splits_artist_from_title_at_the_first_separator() {
+        let split = |title| split_artist_title(title, " - ");
+        assert_eq!(
+            split("Daft Punk - Get Lucky - Radio Edit"),
+            Some((
+                "Daft Punk".to_string(),
+                "Get Lucky - Radio Edit".to_string()
+            ))
+        );
+        assert_eq!(split("Get Lucky"), None);
+        assert_eq!(split(" - Get Lucky"), None);
+        assert_eq!(split_artist_title("Daft Punk - Get Lucky", ""), None);
+    }
+}
@@ -1,27 +1,177 @@
+use crate::config::SearchQueryMode;
+use crate::model::TrackLinks;
+use crate::text::clean_metadata;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::collections::VecDeque;
+use std::sync::Mutex;
 
-pub fn apple_music_search_url(artist: &str, title: &str) -> String {
-    let q = format!("{} {}", artist, title);
-    let encoded = utf8_percent_encode(&q, NON_ALPHANUMERIC).to_string();
+const LINK_CACHE_CAPACITY: usize = 8;
+
+static LINK_CACHE: Mutex<LinkCache> = Mutex::new(LinkCache::new(LINK_CACHE_CAPACITY));
+
+pub fn search_query(artist: &str, title: &str, mode: SearchQueryMode) -> String {
+    match mode {
+        SearchQueryMode::Raw => format!("{artist} {title}"),
+        SearchQueryMode::Clean => clean_metadata(&format!(
+            "{} {}",
+            strip_credits(artist),
+            strip_credits(title)
+        )),
+    }
+}
+
+/// Removes `(...)`/`[...]` segments and trailing `feat.`/`ft.` credits.
+fn strip_credits(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+
+    let lower = out.to_lowercase();
+    let cut = [" feat. ", " feat ", " ft. ", " featuring "]
+        .iter()
+        .filter_map(|marker| lower.find(marker))
+        .min();
+    match cut {
+        // Lowercasing can change byte lengths, so only cut on a matching boundary.
+        Some(idx) if out.is_char_boundary(idx) && lower.len() == out.len() => {
+            out.truncate(idx);
+            out
+        }
+        _ => out,
+    }
+}
+
+pub fn apple_music_search_url(artist: &str, title: &str, mode: SearchQueryMode) -> String {
+    let q = search_query(artist, title, mode);
+    // Apple Music reads the term as a form field, where spaces are `+`.
+    let encoded = utf8_percent_encode(&q, NON_ALPHANUMERIC)
+        .to_string()
+        .replace("%20", "+");
     format!("https://music.apple.com/us/search?term={encoded}")
 }
 
-pub fn spotify_search_url(artist: &str, title: &str) -> String {
-    let q = format!("{} {}", artist, title);
+pub fn spotify_search_url(artist: &str, title: &str, mode: SearchQueryMode) -> String {
+    let q = search_query(artist, title, mode);
     let encoded = utf8_percent_encode(&q, NON_ALPHANUMERIC).to_string();
     format!("https://open.spotify.com/search/{encoded}")
 }
 
+/// Search links for a track, reusing recently built ones so steady polling
+/// doesn't re-encode the same query every second.
+pub fn track_links(artist: &str, title: &str, mode: SearchQueryMode) -> TrackLinks {
+    match LINK_CACHE.lock() {
+        Ok(mut cache) => cache.get_or_build(artist, title, mode),
+        Err(_) => build_links(artist, title, mode),
+    }
+}
+
+fn build_links(artist: &str, title: &str, mode: SearchQueryMode) -> TrackLinks {
+    TrackLinks {
+        apple_music: Some(apple_music_search_url(artist, title, mode)),
+        spotify_search: Some(spotify_search_url(artist, title, mode)),
+    }
+}
+
+struct LinkCache {
+    capacity: usize,
+    entries: VecDeque<((String, String, SearchQueryMode), TrackLinks)>,
+}
+
+impl LinkCache {
+    const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn get_or_build(&mut self, artist: &str, title: &str, mode: SearchQueryMode) -> TrackLinks {
+        if let Some(idx) = self
+            .entries
+            .iter()
+            .position(|((a, t, m), _)| a == artist && t == title && *m == mode)
+        {
+            let entry = self.entries.remove(idx).expect("index in bounds");
+            let links = entry.1.clone();
+            self.entries.push_front(entry);
+            return links;
+        }
+
+        let links = build_links(artist, title, mode);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries
+            .push_front(((artist.to_string(), title.to_string(), mode), links.clone()));
+        links
+    }
+
+    #[cfg(test)]
+    fn contains(&self, artist: &str, title: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|((a, t, _), _)| a == artist && t == title)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{apple_music_search_url, spotify_search_url};
+    use super::{apple_music_search_url, search_query, spotify_search_url, LinkCache};
+    use crate::config::SearchQueryMode;
 
     #[test]
     fn url_builder_encodes_queries() {
-        let apple = apple_music_search_url("Daft Punk", "Get Lucky");
-        let spotify = spotify_search_url("AC/DC", "Back In Black");
+        let apple = apple_music_search_url("Daft Punk", "Get Lucky", SearchQueryMode::Raw);
+        let spotify = spotify_search_url("AC/DC", "Back In Black", SearchQueryMode::Raw);
 
-        assert!(apple.contains("Daft%20Punk%20Get%20Lucky"));
+        assert!(apple.contains("term=Daft+Punk+Get+Lucky"));
         assert!(spotify.contains("AC%2FDC%20Back%20In%20Black"));
     }
+
+    #[test]
+    fn clean_queries_drop_credits_and_brackets() {
+        let title = "Song (feat. Y) [Remastered]";
+        assert_eq!(
+            search_query("Artist", title, SearchQueryMode::Raw),
+            "Artist Song (feat. Y) [Remastered]"
+        );
+        assert_eq!(
+            search_query("Artist", title, SearchQueryMode::Clean),
+            "Artist Song"
+        );
+        assert_eq!(
+            search_query(
+                "Artist ft. Other",
+                "Canción - Remix",
+                SearchQueryMode::Clean
+            ),
+            "Artist Canción - Remix"
+        );
+    }
+
+    #[test]
+    fn link_cache_reuses_and_evicts() {
+        let raw = SearchQueryMode::Raw;
+        let mut cache = LinkCache::new(2);
+        let first = cache.get_or_build("Daft Punk", "Get Lucky", raw);
+        let again = cache.get_or_build("Daft Punk", "Get Lucky", raw);
+        assert_eq!(first, again);
+        assert_eq!(cache.entries.len(), 1);
+
+        let other = cache.get_or_build("AC/DC", "Back In Black", raw);
+        assert_ne!(first, other);
+        assert!(other.spotify_search.unwrap().contains("AC%2FDC"));
+
+        let _ = cache.get_or_build("Daft Punk", "Get Lucky", raw);
+        let _ = cache.get_or_build("Justice", "D.A.N.C.E.", raw);
+        assert!(cache.contains("Daft Punk", "Get Lucky"));
+        assert!(!cache.contains("AC/DC", "Back In Black"));
+    }
 }
@@ -1,17 +1,56 @@
-use presence_bridge_core::{AppConfig, PlaybackState, Track};
-use presence_bridge_providers::ProviderSnapshot;
+use presence_bridge_core::{
+    clamp_position, urls, ActivityKind, AppConfig, AssetChoice, IdlePresenceConfig, PlaybackState,
+    PresenceLayout, SearchQueryMode, TimestampMode, Track, TrackIdentity, TrackLinks,
+};
+use presence_bridge_providers::{ProviderError, ProviderSnapshot};
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub mod locale;
+mod template;
+
+/// Maximum byte length Discord accepts for activity `details` and `state`.
+pub const DISCORD_TEXT_LIMIT: usize = 128;
+/// Maximum length, in characters, Discord accepts for a button label.
+pub const DISCORD_BUTTON_LABEL_LIMIT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DiffKind {
     TrackChanged,
     StateChanged,
     Nothing,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecisionReason {
+    /// Track or play/pause state changed since the last tick.
+    ImmediateChange,
+    /// Nothing changed, but the keepalive interval elapsed while playing.
+    Keepalive,
+    /// The presence content differs from the last send and the minimum update interval elapsed.
+    ContentChanged,
+    /// A resend was requested through `EventEngine::force_resend`.
+    Forced,
+    /// A play/pause flip arrived within the debounce window.
+    Debounced,
+    /// Nothing changed and the presence matches what was last sent.
+    HashUnchanged,
+    /// The presence differs from the last send but no resend is due yet.
+    Throttled,
+    /// The provider errored; the last presence is held for the grace period.
+    ProviderErrorHold,
+    /// The track went away; clearing waits out `clear_grace` in case the next one starts.
+    ClearDeferred,
+    /// The active track went away.
+    Cleared,
+    /// No track before and no track now.
+    NoTrack,
+    /// Nothing is playing, so the configured idle presence was sent.
+    IdlePresence,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PresenceButton {
     pub label: String,
@@ -31,20 +70,26 @@ pub struct PresenceState {
     pub small_image: Option<String>,
     pub small_text: Option<String>,
     pub buttons: Vec<PresenceButton>,
+    pub party_size: Option<u32>,
+    pub party_max: Option<u32>,
+    pub instance: Option<bool>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EngineAction {
-    Send(PresenceState),
+    Send(Box<PresenceState>),
     Clear,
     None,
 }
 
-#[derive(Debug, Clone)]
+/// One tick's decision; serializes with `next_poll_in` as `next_poll_ms` for diagnostics.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EngineOutput {
     pub action: EngineAction,
+    #[serde(rename = "next_poll_ms", with = "duration_ms")]
     pub next_poll_in: Duration,
     pub diff: DiffKind,
+    pub reason: DecisionReason,
 }
 
 #[derive(Debug, Clone)]
@@ -52,13 +97,44 @@ pub struct EngineConfig {
     pub playing_poll: Duration,
     pub paused_poll: Duration,
     pub stopped_poll: Duration,
+    pub provider_poll_floor: Vec<(String, Duration)>,
     pub min_presence_update_interval: Duration,
+    pub presence_keepalive: Duration,
     pub debounce: Duration,
+    pub provider_error_grace: Duration,
+    pub clear_grace: Duration,
+    pub adaptive_poll_min: Duration,
+    pub min_track_duration: Duration,
+    pub min_visible: Duration,
     pub enable_buttons: bool,
-    pub large_image: Option<String>,
+    pub button_prefix: Option<String>,
+    pub show_timestamp: bool,
+    pub party_size: Option<u32>,
+    pub party_max: Option<u32>,
+    pub instance: Option<bool>,
+    pub large_image: Option<AssetChoice>,
     pub large_text: Option<String>,
+    pub large_image_paused: Option<String>,
+    pub large_text_paused: Option<String>,
     pub small_play_image: Option<String>,
     pub small_pause_image: Option<String>,
+    pub small_play_text: Option<String>,
+    pub small_pause_text: Option<String>,
+    pub locale: String,
+    pub presence_layout: PresenceLayout,
+    pub track_identity: TrackIdentity,
+    pub timestamp_mode: TimestampMode,
+    pub name_from_source: bool,
+    pub compact: bool,
+    pub small_text_source: bool,
+    pub show_position_when_paused: bool,
+    pub hash_start_timestamp: bool,
+    pub state_template: Option<String>,
+    /// How the search links behind the buttons phrase their query.
+    pub search_query: SearchQueryMode,
+    pub idle_presence: Option<IdlePresenceConfig>,
+    /// Lowercased player-name substrings and the activity type they select.
+    pub player_activity: Vec<(String, ActivityKind)>,
 }
 
 impl EngineConfig {
@@ -67,19 +143,59 @@ impl EngineConfig {
             playing_poll: Duration::from_millis(cfg.intervals.playing_poll_ms),
             paused_poll: Duration::from_millis(cfg.intervals.paused_poll_ms),
             stopped_poll: Duration::from_millis(cfg.intervals.stopped_poll_ms),
+            provider_poll_floor: cfg
+                .intervals
+                .provider_poll_floor_ms
+                .iter()
+                .map(|(name, ms)| (name.clone(), Duration::from_millis(*ms)))
+                .collect(),
             min_presence_update_interval: Duration::from_millis(
                 cfg.intervals.presence_min_update_ms,
             ),
+            presence_keepalive: Duration::from_millis(cfg.intervals.presence_keepalive_ms),
             debounce: Duration::from_millis(cfg.intervals.debounce_ms),
+            provider_error_grace: Duration::from_millis(cfg.intervals.provider_error_grace_ms),
+            clear_grace: Duration::from_millis(cfg.intervals.clear_grace_ms),
+            adaptive_poll_min: Duration::from_millis(cfg.intervals.adaptive_poll_min_ms),
+            min_track_duration: Duration::from_millis(cfg.min_track_duration_ms),
+            min_visible: Duration::from_millis(cfg.min_visible_ms),
             enable_buttons: cfg.enable_buttons,
+            button_prefix: cfg.button_prefix.clone(),
+            show_timestamp: cfg.show_timestamp,
+            party_size: cfg.party_size,
+            party_max: cfg.party_max,
+            instance: cfg.activity_instance,
             large_image: cfg.assets.large_image.clone(),
             large_text: cfg.assets.large_text.clone(),
+            large_image_paused: cfg.assets.large_image_paused.clone(),
+            large_text_paused: cfg.assets.large_text_paused.clone(),
             small_play_image: cfg.assets.small_play_image.clone(),
             small_pause_image: cfg.assets.small_pause_image.clone(),
+            small_play_text: cfg.assets.small_play_text.clone(),
+            small_pause_text: cfg.assets.small_pause_text.clone(),
+            locale: cfg.locale.clone(),
+            presence_layout: cfg.presence_layout,
+            track_identity: cfg.track_identity,
+            timestamp_mode: cfg.timestamp_mode,
+            name_from_source: cfg.name_from_source,
+            compact: cfg.compact,
+            small_text_source: cfg.small_text_source,
+            show_position_when_paused: cfg.show_position_when_paused,
+            hash_start_timestamp: cfg.hash_start_timestamp,
+            state_template: cfg.state_template.clone(),
+            search_query: cfg.search_query,
+            idle_presence: cfg.idle_presence.enabled.then(|| cfg.idle_presence.clone()),
+            player_activity: cfg
+                .player_activity
+                .iter()
+                .map(|(player, kind)| (player.to_lowercase(), *kind))
+                .collect(),
         }
     }
 }
 
+pub type TrackCallback = Box<dyn Fn(&Track) + Send>;
+
 pub struct EventEngine {
     cfg: EngineConfig,
     last_track: Option<Track>,
@@ -87,6 +203,15 @@ pub struct EventEngine {
     last_sent_at: Option<Instant>,
     last_state_flip_at: Option<Instant>,
     stable_start_timestamp: Option<i64>,
+    /// Start of the current listening session in `TimestampMode::Session`.
+    session_start: Option<i64>,
+    provider_error_since: Option<Instant>,
+    clear_pending_since: Option<Instant>,
+    adaptive_poll: Option<Duration>,
+    /// The short track currently being held back and when it started.
+    short_track_since: Option<(String, Instant)>,
+    force_send: bool,
+    on_track_change: Option<TrackCallback>,
 }
 
 impl EventEngine {
@@ -98,11 +223,48 @@ impl EventEngine {
             last_sent_at: None,
             last_state_flip_at: None,
             stable_start_timestamp: None,
+            session_start: None,
+            provider_error_since: None,
+            clear_pending_since: None,
+            adaptive_poll: None,
+            short_track_since: None,
+            force_send: false,
+            on_track_change: None,
         }
     }
 
     pub fn update_config(&mut self, cfg: EngineConfig) {
+        // Starts kept under the other mode mean something else; derive them afresh.
+        if cfg.timestamp_mode != self.cfg.timestamp_mode {
+            self.session_start = None;
+            self.stable_start_timestamp = None;
+        }
         self.cfg = cfg;
+        // Makes the next tick resend, playing or paused, so config changes show up
+        // immediately.
+        self.last_sent_at = None;
+    }
+
+    /// Registers a callback invoked once for every newly committed track.
+    pub fn set_on_track_change(&mut self, callback: TrackCallback) {
+        self.on_track_change = Some(callback);
+    }
+
+    /// Sends the current presence on the next tick even if nothing changed.
+    pub fn force_resend(&mut self) {
+        self.force_send = true;
+    }
+
+    /// Clears presence on shutdown if anything is currently shown.
+    pub fn shutdown(&mut self) -> EngineAction {
+        let shown = self.last_sent_hash.take().is_some() || self.last_track.is_some();
+        self.last_track = None;
+        self.session_start = None;
+        if shown {
+            EngineAction::Clear
+        } else {
+            EngineAction::None
+        }
     }
 
     pub fn tick(
@@ -111,14 +273,70 @@ impl EventEngine {
         now_instant: Instant,
         now_system: SystemTime,
     ) -> EngineOutput {
-        let next_poll_in = self.next_poll(snapshot.state);
+        // A lasting failure (permission denied, player gone) clears like a stop; only
+        // transient ones are ridden out.
+        let transient_error = snapshot
+            .last_error
+            .as_ref()
+            .is_some_and(ProviderError::is_transient);
+        if snapshot.track.is_none() && transient_error {
+            if let Some(prev) = &self.last_track {
+                let since = *self.provider_error_since.get_or_insert(now_instant);
+                if now_instant.duration_since(since) < self.cfg.provider_error_grace {
+                    let held_state = if prev.is_playing {
+                        PlaybackState::Playing
+                    } else {
+                        PlaybackState::Paused
+                    };
+                    return EngineOutput {
+                        action: EngineAction::None,
+                        next_poll_in: self.next_poll(held_state, snapshot.provider_name),
+                        diff: DiffKind::Nothing,
+                        reason: DecisionReason::ProviderErrorHold,
+                    };
+                }
+            }
+        } else {
+            self.provider_error_since = None;
+        }
 
-        let current_track = snapshot.track;
+        if snapshot.track.is_none() {
+            self.short_track_since = None;
+        }
+        // A position jump while playing is a seek only when the provider's position is
+        // live; elsewhere it is more likely a stale value catching up.
+        let follows_seeks =
+            snapshot.capabilities.seek_signals || snapshot.capabilities.accurate_position;
+        let current_track = snapshot
+            .track
+            .filter(|t| self.admit_short_track(t, now_instant));
         let diff = self.compute_diff(current_track.as_ref());
 
+        if !self.cfg.adaptive_poll_min.is_zero() {
+            self.adaptive_poll = match diff {
+                DiffKind::TrackChanged | DiffKind::StateChanged => Some(self.cfg.adaptive_poll_min),
+                DiffKind::Nothing => self.adaptive_poll.map(|d| d.saturating_mul(2)),
+            };
+        }
+        let next_poll_in = self.next_poll(snapshot.state, snapshot.provider_name);
+
+        if current_track.is_none() && self.last_track.is_some() && !self.cfg.clear_grace.is_zero() {
+            let since = *self.clear_pending_since.get_or_insert(now_instant);
+            let waited = now_instant.duration_since(since);
+            if waited < self.cfg.clear_grace {
+                return EngineOutput {
+                    action: EngineAction::None,
+                    next_poll_in: next_poll_in.min(self.cfg.clear_grace - waited),
+                    diff: DiffKind::Nothing,
+                    reason: DecisionReason::ClearDeferred,
+                };
+            }
+        }
+        self.clear_pending_since = None;
+
         let jitter_state_flip = match (&self.last_track, &current_track) {
             (Some(prev), Some(curr))
-                if prev.id == curr.id && prev.is_playing != curr.is_playing =>
+                if self.same_track(prev, curr) && prev.is_playing != curr.is_playing =>
             {
                 let by_flip = self
                     .last_state_flip_at
@@ -141,75 +359,158 @@ impl EventEngine {
                 action: EngineAction::None,
                 next_poll_in,
                 diff: DiffKind::Nothing,
+                reason: DecisionReason::Debounced,
             };
         }
 
         if let Some(ref track) = current_track {
-            if track.is_playing {
-                match (&self.last_track, self.stable_start_timestamp) {
-                    (Some(prev), Some(stable)) if prev.id == track.id => {
-                        self.stable_start_timestamp = Some(stable);
+            // Paused ticks keep the last start so a resume can snap back to it.
+            if track.is_playing && self.cfg.timestamp_mode == TimestampMode::Session {
+                if self.session_start.is_none() {
+                    self.session_start = compute_start_timestamp(track, now_system, None);
+                }
+                self.stable_start_timestamp = self.session_start;
+            } else if track.is_playing {
+                self.stable_start_timestamp = match (&self.last_track, self.stable_start_timestamp)
+                {
+                    (Some(prev), Some(stable))
+                        if self.same_track(prev, track) && prev.is_playing =>
+                    {
+                        if follows_seeks {
+                            compute_start_timestamp(track, now_system, Some(stable))
+                        } else {
+                            Some(stable)
+                        }
                     }
-                    _ => {
-                        self.stable_start_timestamp = compute_start_timestamp(track, now_system);
+                    (Some(prev), previous) if self.same_track(prev, track) => {
+                        compute_start_timestamp(track, now_system, previous)
                     }
-                }
-            } else {
-                self.stable_start_timestamp = None;
+                    _ => compute_start_timestamp(track, now_system, None),
+                };
             }
         } else {
             self.stable_start_timestamp = None;
+            self.session_start = None;
         }
 
-        let action = match current_track.as_ref() {
+        let (action, reason) = match current_track.as_ref() {
             Some(track) => {
                 let presence = self.to_presence(track);
-                let hash = hash_presence(&presence);
+                let hash = hash_presence(&presence, self.cfg.hash_start_timestamp);
                 let immediate_change =
                     diff == DiffKind::TrackChanged || diff == DiffKind::StateChanged;
+                // With timestamps set Discord animates the progress bar on its own, so
+                // only a long safety keepalive is needed to recover from dropped frames.
+                let keepalive_interval = if presence.start_timestamp.is_some() {
+                    self.cfg.presence_keepalive
+                } else {
+                    self.cfg.min_presence_update_interval
+                };
                 let due_keepalive = self
                     .last_sent_at
-                    .map(|at| {
-                        now_instant.duration_since(at) >= self.cfg.min_presence_update_interval
-                    })
+                    .map(|at| now_instant.duration_since(at) >= keepalive_interval)
                     .unwrap_or(true);
+                let due_content_update = self.last_sent_hash != Some(hash)
+                    && self.last_sent_at.is_none_or(|at| {
+                        now_instant.duration_since(at) >= self.cfg.min_presence_update_interval
+                    });
 
-                if immediate_change || (track.is_playing && due_keepalive) {
-                    if self.last_sent_hash != Some(hash) || due_keepalive || immediate_change {
-                        self.last_sent_hash = Some(hash);
-                        self.last_sent_at = Some(now_instant);
-                        EngineAction::Send(presence)
+                if immediate_change
+                    || self.force_send
+                    || (track.is_playing && due_keepalive)
+                    || due_content_update
+                {
+                    self.last_sent_hash = Some(hash);
+                    self.last_sent_at = Some(now_instant);
+                    let reason = if immediate_change {
+                        DecisionReason::ImmediateChange
+                    } else if self.force_send {
+                        DecisionReason::Forced
+                    } else if due_content_update {
+                        DecisionReason::ContentChanged
                     } else {
-                        EngineAction::None
-                    }
+                        DecisionReason::Keepalive
+                    };
+                    self.force_send = false;
+                    (EngineAction::Send(Box::new(presence)), reason)
+                } else if self.last_sent_hash == Some(hash) {
+                    (EngineAction::None, DecisionReason::HashUnchanged)
                 } else {
-                    EngineAction::None
+                    (EngineAction::None, DecisionReason::Throttled)
+                }
+            }
+            None if self.cfg.idle_presence.is_some() => {
+                let presence = self.idle_presence();
+                let hash = hash_presence(&presence, true);
+                if self.force_send || self.last_sent_hash != Some(hash) {
+                    self.last_sent_hash = Some(hash);
+                    self.last_sent_at = Some(now_instant);
+                    self.force_send = false;
+                    (
+                        EngineAction::Send(Box::new(presence)),
+                        DecisionReason::IdlePresence,
+                    )
+                } else {
+                    (EngineAction::None, DecisionReason::NoTrack)
                 }
             }
             None => {
                 if self.last_track.is_some() {
                     self.last_sent_hash = None;
                     self.last_sent_at = Some(now_instant);
-                    EngineAction::Clear
+                    (EngineAction::Clear, DecisionReason::Cleared)
                 } else {
-                    EngineAction::None
+                    (EngineAction::None, DecisionReason::NoTrack)
                 }
             }
         };
 
         if let (Some(prev), Some(curr)) = (&self.last_track, &current_track) {
-            if prev.id == curr.id && prev.is_playing != curr.is_playing {
+            if self.same_track(prev, curr) && prev.is_playing != curr.is_playing {
                 self.last_state_flip_at = Some(now_instant);
             }
         }
 
+        if diff == DiffKind::TrackChanged {
+            if let (Some(callback), Some(track)) = (&self.on_track_change, &current_track) {
+                callback(track);
+            }
+        }
+
         self.last_track = current_track;
 
         EngineOutput {
             action,
             next_poll_in,
             diff,
+            reason,
+        }
+    }
+
+    fn is_too_short(&self, track: &Track) -> bool {
+        track
+            .duration_ms
+            .is_some_and(|d| Duration::from_millis(d) < self.cfg.min_track_duration)
+    }
+
+    /// Short tracks are dropped, unless `min_visible` is set and they have stayed current
+    /// that long: an interlude that is actually listened to then shows like any track.
+    fn admit_short_track(&mut self, track: &Track, now: Instant) -> bool {
+        if !self.is_too_short(track) {
+            self.short_track_since = None;
+            return true;
         }
+        if self.cfg.min_visible.is_zero() {
+            return false;
+        }
+        let since = match &self.short_track_since {
+            Some((id, since)) if *id == track.id => *since,
+            _ => {
+                self.short_track_since = Some((track.id.clone(), now));
+                now
+            }
+        };
+        now.duration_since(since) >= self.cfg.min_visible
     }
 
     fn compute_diff(&self, current: Option<&Track>) -> DiffKind {
@@ -217,7 +518,7 @@ impl EventEngine {
             (None, None) => DiffKind::Nothing,
             (None, Some(_)) | (Some(_), None) => DiffKind::TrackChanged,
             (Some(prev), Some(curr)) => {
-                if prev.id != curr.id {
+                if !self.same_track(prev, curr) {
                     DiffKind::TrackChanged
                 } else if prev.is_playing != curr.is_playing {
                     DiffKind::StateChanged
@@ -228,87 +529,365 @@ impl EventEngine {
         }
     }
 
-    fn to_presence(&self, track: &Track) -> PresenceState {
-        let details = format!("{} — {}", track.artist, track.title);
-        let state = if track.is_playing {
+    fn same_track(&self, a: &Track, b: &Track) -> bool {
+        match self.cfg.track_identity {
+            TrackIdentity::Id => a.id == b.id,
+            TrackIdentity::Fingerprint => a.fingerprint() == b.fingerprint(),
+        }
+    }
+
+    fn activity_kind(&self, track: &Track) -> ActivityKind {
+        let Some(player) = track.player.as_deref().map(str::to_lowercase) else {
+            return ActivityKind::Listening;
+        };
+        self.cfg
+            .player_activity
+            .iter()
+            .find(|(needle, _)| player.contains(needle.as_str()))
+            .map(|(_, kind)| *kind)
+            .unwrap_or_default()
+    }
+
+    /// Picks the details and state lines from the activity kind, `presence_layout` and
+    /// `state_template`.
+    fn presence_lines(&self, track: &Track, kind: ActivityKind) -> (String, String) {
+        let strings = locale::strings_for(&self.cfg.locale);
+        let status = if !track.is_playing && self.cfg.show_position_when_paused {
+            paused_with_position(strings.paused, track)
+        } else if track.is_playing {
+            strings.playing.to_string()
+        } else {
+            strings.paused.to_string()
+        };
+        // Podcasts show the episode over its show rather than artist and title.
+        if track.is_podcast && kind == ActivityKind::Listening {
+            let state = if track.is_playing {
+                track.album.clone().unwrap_or_else(|| track.artist.clone())
+            } else {
+                status
+            };
+            return (track.title.clone(), state);
+        }
+        // Videos rarely carry a real artist, so the title stands alone.
+        if kind == ActivityKind::Watching {
+            return (track.title.clone(), status);
+        }
+
+        let layout = self.cfg.presence_layout;
+        let has_artist = !track.artist.is_empty();
+        let details = match layout {
+            PresenceLayout::ArtistTitle if has_artist => {
+                format!("{} — {}", track.artist, track.title)
+            }
+            _ => track.title.clone(),
+        };
+        let state = if !track.is_playing {
+            status
+        } else if let Some(tpl) = &self.cfg.state_template {
+            template::render(
+                tpl,
+                &[
+                    ("album", track.album.clone()),
+                    ("artist", has_artist.then(|| track.artist.clone())),
+                    ("title", Some(track.title.clone())),
+                    ("track_number", track.track_number.map(|n| n.to_string())),
+                    ("year", track.year.map(|y| y.to_string())),
+                ],
+            )
+        } else if layout == PresenceLayout::TitleArtist && has_artist {
+            track.artist.clone()
+        } else {
             track
                 .album
                 .as_ref()
-                .map(|a| format!("on {a}"))
-                .unwrap_or_else(|| "Playing".to_string())
+                .map(|a| strings.on_album(a))
+                .unwrap_or(status)
+        };
+        (details, state)
+    }
+
+    fn small_text(&self, track: &Track) -> Option<String> {
+        let text = if track.is_playing {
+            self.cfg.small_play_text.clone()
         } else {
-            "Paused".to_string()
+            self.cfg.small_pause_text.clone()
         };
+        if !self.cfg.small_text_source {
+            return text;
+        }
+        let source = track.source.display_name();
+        Some(match text {
+            Some(text) => format!("{text} · {source}"),
+            None => source.to_string(),
+        })
+    }
+
+    fn button_label(&self, label: &str) -> String {
+        let prefix = self.cfg.button_prefix.as_deref().unwrap_or_default();
+        truncate_chars(format!("{prefix}{label}"), DISCORD_BUTTON_LABEL_LIMIT)
+    }
+
+    /// The track's search links, rebuilt from its metadata when clean queries are set.
+    /// Providers always build raw ones; a link they left out stays out.
+    fn search_links(&self, track: &Track) -> TrackLinks {
+        match self.cfg.search_query {
+            SearchQueryMode::Raw => track.links.clone(),
+            mode => {
+                let built = urls::track_links(&track.artist, &track.title, mode);
+                TrackLinks {
+                    apple_music: track.links.apple_music.as_ref().and(built.apple_music),
+                    spotify_search: track
+                        .links
+                        .spotify_search
+                        .as_ref()
+                        .and(built.spotify_search),
+                }
+            }
+        }
+    }
+
+    fn to_presence(&self, track: &Track) -> PresenceState {
+        let kind = self.activity_kind(track);
+        let (details, state) = self.presence_lines(track, kind);
 
         let mut buttons = Vec::new();
         if self.cfg.enable_buttons {
-            if let Some(url) = &track.links.apple_music {
+            let links = self.search_links(track);
+            if let Some(url) = links.apple_music {
                 buttons.push(PresenceButton {
-                    label: "Open/Search Apple Music".to_string(),
-                    url: url.clone(),
+                    label: self.button_label("Open/Search Apple Music"),
+                    url,
                 });
             }
-            if let Some(url) = &track.links.spotify_search {
+            if let Some(url) = links.spotify_search {
                 buttons.push(PresenceButton {
-                    label: "Search Spotify".to_string(),
-                    url: url.clone(),
+                    label: self.button_label("Search Spotify"),
+                    url,
                 });
             }
         }
         buttons.truncate(2);
 
-        PresenceState {
-            activity_type: 2,
-            name: "Listening".to_string(),
-            details,
-            state,
-            start_timestamp: if track.is_playing {
+        let presence = PresenceState {
+            activity_type: kind.discord_type(),
+            name: if self.cfg.name_from_source {
+                track.source.display_name()
+            } else {
+                match kind {
+                    ActivityKind::Listening if track.is_podcast => "Podcast",
+                    ActivityKind::Listening => "Listening",
+                    ActivityKind::Watching => "Watching",
+                }
+            }
+            .to_string(),
+            details: truncate_text(details, DISCORD_TEXT_LIMIT),
+            state: truncate_text(state, DISCORD_TEXT_LIMIT),
+            start_timestamp: if track.is_playing && self.cfg.show_timestamp {
                 self.stable_start_timestamp
             } else {
                 None
             },
             is_playing: track.is_playing,
-            large_image: self.cfg.large_image.clone(),
-            large_text: self.cfg.large_text.clone(),
+            large_image: self
+                .cfg
+                .large_image_paused
+                .clone()
+                .filter(|_| !track.is_playing)
+                .or_else(|| {
+                    self.cfg
+                        .large_image
+                        .as_ref()
+                        .and_then(|choice| choice.pick(&track.id))
+                        .map(str::to_string)
+                }),
+            large_text: self
+                .cfg
+                .large_text_paused
+                .clone()
+                .filter(|_| !track.is_playing)
+                .or_else(|| self.cfg.large_text.clone()),
             small_image: if track.is_playing {
                 self.cfg.small_play_image.clone()
             } else {
                 self.cfg.small_pause_image.clone()
             },
-            small_text: Some(if track.is_playing {
-                "Playing".to_string()
-            } else {
-                "Paused".to_string()
-            }),
+            small_text: self.small_text(track),
             buttons,
+            party_size: self.cfg.party_size,
+            party_max: self.cfg.party_max,
+            instance: self.cfg.instance,
+        };
+        if !self.cfg.compact {
+            return presence;
+        }
+        // An empty state is left out of the payload by the RPC client.
+        PresenceState {
+            state: String::new(),
+            large_image: None,
+            large_text: None,
+            small_image: None,
+            small_text: None,
+            buttons: Vec::new(),
+            party_size: None,
+            party_max: None,
+            ..presence
+        }
+    }
+
+    fn idle_presence(&self) -> PresenceState {
+        let idle = self.cfg.idle_presence.clone().unwrap_or_default();
+        PresenceState {
+            activity_type: 2,
+            name: "Listening".to_string(),
+            details: truncate_text(idle.details, DISCORD_TEXT_LIMIT),
+            state: truncate_text(idle.state.unwrap_or_default(), DISCORD_TEXT_LIMIT),
+            start_timestamp: None,
+            is_playing: false,
+            large_image: self
+                .cfg
+                .large_image
+                .as_ref()
+                .and_then(AssetChoice::first)
+                .map(str::to_string),
+            large_text: self.cfg.large_text.clone(),
+            small_image: None,
+            small_text: None,
+            buttons: Vec::new(),
+            party_size: None,
+            party_max: None,
+            instance: self.cfg.instance,
         }
     }
 
-    fn next_poll(&self, state: PlaybackState) -> Duration {
-        match state {
+    fn next_poll(&self, state: PlaybackState, provider: &str) -> Duration {
+        let base = match state {
             PlaybackState::Playing => self.cfg.playing_poll,
             PlaybackState::Paused => self.cfg.paused_poll,
             PlaybackState::Stopped => self.cfg.stopped_poll,
-        }
+        };
+        let base = match self.adaptive_poll {
+            Some(adaptive) if !self.cfg.adaptive_poll_min.is_zero() => base.min(adaptive),
+            _ => base,
+        };
+        self.cfg
+            .provider_poll_floor
+            .iter()
+            .find(|(name, _)| name == provider)
+            .map_or(base, |(_, floor)| base.max(*floor))
     }
 }
 
-fn compute_start_timestamp(track: &Track, now_system: SystemTime) -> Option<i64> {
+/// Start timestamps within this many seconds of the previous one are treated as jitter.
+const START_TIMESTAMP_TOLERANCE_SECS: i64 = 2;
+
+fn compute_start_timestamp(
+    track: &Track,
+    now_system: SystemTime,
+    previous: Option<i64>,
+) -> Option<i64> {
     if !track.is_playing {
         return None;
     }
     let now_epoch = now_system.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
-    let pos_sec = (track.position_ms.unwrap_or(0) / 1_000) as i64;
-    Some(now_epoch - pos_sec)
+    let start = now_epoch - position_secs(track);
+    match previous {
+        Some(prev) if (start - prev).abs() <= START_TIMESTAMP_TOLERANCE_SECS => Some(prev),
+        // Pausing or seeking can't move the start earlier by more than the track's length;
+        // only the wall clock stepping backwards (NTP, wake from sleep) can.
+        Some(prev)
+            if track
+                .duration_ms
+                .is_some_and(|d| prev - start > (d / 1_000) as i64) =>
+        {
+            Some(prev)
+        }
+        _ => Some(start),
+    }
+}
+
+/// `Paused · 1:23 / 3:45`, or just the elapsed time when the duration is unknown.
+fn paused_with_position(paused: &str, track: &Track) -> String {
+    let Some(position_ms) = clamp_position(track.position_ms, track.duration_ms) else {
+        return paused.to_string();
+    };
+    match track.duration_ms.filter(|d| *d > 0) {
+        Some(duration_ms) => format!(
+            "{paused} · {} / {}",
+            format_clock(position_ms),
+            format_clock(duration_ms)
+        ),
+        None => format!("{paused} · {}", format_clock(position_ms)),
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour up.
+fn format_clock(ms: u64) -> String {
+    let secs = ms / 1_000;
+    let (h, m, s) = (secs / 3_600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+/// Normalizes the reported position to the nearest whole second. Providers report at
+/// different granularities (MPRIS in µs, macOS in ms, some Windows players in whole
+/// seconds), so rounding in one place keeps the derived start timestamp comparable
+/// across platforms.
+fn position_secs(track: &Track) -> i64 {
+    let position_ms = clamp_position(track.position_ms, track.duration_ms).unwrap_or(0);
+    (position_ms.saturating_add(500) / 1_000) as i64
+}
+
+mod duration_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+fn truncate_text(mut text: String, limit: usize) -> String {
+    const ELLIPSIS: char = '…';
+    if text.len() <= limit {
+        return text;
+    }
+    let mut end = limit.saturating_sub(ELLIPSIS.len_utf8());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.push(ELLIPSIS);
+    text
+}
+
+/// Like [`truncate_text`], but for limits Discord counts in characters.
+fn truncate_chars(text: String, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text;
+    }
+    let mut out: String = text.chars().take(limit.saturating_sub(1)).collect();
+    out.push('…');
+    out
 }
 
-fn hash_presence(state: &PresenceState) -> u64 {
+/// Discord animates the progress bar from `start_timestamp`, so leaving it out
+/// (`with_timestamp == false`) keeps a recomputed start from triggering a resend.
+fn hash_presence(state: &PresenceState, with_timestamp: bool) -> u64 {
     let mut hasher = DefaultHasher::new();
     state.details.hash(&mut hasher);
     state.activity_type.hash(&mut hasher);
     state.name.hash(&mut hasher);
     state.state.hash(&mut hasher);
-    state.start_timestamp.hash(&mut hasher);
+    if with_timestamp {
+        state.start_timestamp.hash(&mut hasher);
+    }
     state.is_playing.hash(&mut hasher);
     state.large_image.hash(&mut hasher);
     state.large_text.hash(&mut hasher);
@@ -318,14 +897,25 @@ fn hash_presence(state: &PresenceState) -> u64 {
         b.label.hash(&mut hasher);
         b.url.hash(&mut hasher);
     }
+    state.party_size.hash(&mut hasher);
+    state.party_max.hash(&mut hasher);
+    state.instance.hash(&mut hasher);
     hasher.finish()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DiffKind, EngineAction, EngineConfig, EventEngine};
-    use presence_bridge_core::{PlaybackState, SourceApp, Track, TrackLinks};
-    use presence_bridge_providers::ProviderSnapshot;
+    use super::{
+        compute_start_timestamp, format_clock, truncate_text, DecisionReason, DiffKind,
+        EngineAction, EngineConfig, EngineOutput, EventEngine, DISCORD_BUTTON_LABEL_LIMIT,
+        DISCORD_TEXT_LIMIT,
+    };
+    use presence_bridge_core::{
+        ActivityKind, AssetChoice, IdlePresenceConfig, PlaybackState, PresenceLayout,
+        SearchQueryMode, SourceApp, TimestampMode, Track, TrackIdentity, TrackLinks,
+    };
+    use presence_bridge_providers::{ProviderCapabilities, ProviderError, ProviderSnapshot};
+    use std::sync::{Arc, Mutex};
     use std::time::{Duration, Instant, SystemTime};
 
     fn cfg() -> EngineConfig {
@@ -333,13 +923,42 @@ mod tests {
             playing_poll: Duration::from_secs(1),
             paused_poll: Duration::from_secs(7),
             stopped_poll: Duration::from_secs(30),
+            provider_poll_floor: Vec::new(),
             min_presence_update_interval: Duration::from_secs(15),
+            presence_keepalive: Duration::from_secs(300),
             debounce: Duration::from_millis(500),
+            provider_error_grace: Duration::from_secs(10),
+            clear_grace: Duration::ZERO,
+            adaptive_poll_min: Duration::ZERO,
+            min_track_duration: Duration::ZERO,
+            min_visible: Duration::ZERO,
             enable_buttons: true,
-            large_image: Some("app_icon".to_string()),
+            button_prefix: None,
+            show_timestamp: true,
+            party_size: None,
+            party_max: None,
+            instance: None,
+            large_image: Some(AssetChoice::Single("app_icon".to_string())),
             large_text: Some("presence-bridge".to_string()),
+            large_image_paused: None,
+            large_text_paused: None,
             small_play_image: Some("play".to_string()),
             small_pause_image: Some("pause".to_string()),
+            small_play_text: Some("Playing".to_string()),
+            small_pause_text: Some("Paused".to_string()),
+            locale: "en".to_string(),
+            presence_layout: PresenceLayout::ArtistTitle,
+            track_identity: TrackIdentity::Id,
+            timestamp_mode: TimestampMode::PerTrack,
+            name_from_source: false,
+            compact: false,
+            small_text_source: false,
+            show_position_when_paused: false,
+            hash_start_timestamp: true,
+            state_template: None,
+            search_query: SearchQueryMode::Raw,
+            idle_presence: None,
+            player_activity: Vec::new(),
         }
     }
 
@@ -356,18 +975,23 @@ mod tests {
                 title: "Title".to_string(),
                 artist: "Artist".to_string(),
                 album: Some("Album".to_string()),
+                track_number: None,
+                year: None,
                 duration_ms: Some(120_000),
                 position_ms: Some(20_000),
                 is_playing: playing,
                 source: SourceApp::Unknown,
+                player: None,
                 links: TrackLinks {
                     apple_music: Some("https://example.com/apple".to_string()),
                     spotify_search: Some("https://example.com/spotify".to_string()),
                 },
+                is_podcast: false,
                 updated_at: SystemTime::now(),
             }),
             raw_state: None,
             last_error: None,
+            capabilities: ProviderCapabilities::default(),
         }
     }
 
@@ -388,6 +1012,52 @@ mod tests {
         assert!(matches!(second.action, EngineAction::Send(_)));
     }
 
+    #[test]
+    fn provider_ids_for_one_song_share_a_fingerprint() {
+        let track = |id: &str, artist: &str, title: &str| {
+            let mut track = snapshot(id, true).track.unwrap();
+            track.artist = artist.to_string();
+            track.title = title.to_string();
+            track
+        };
+        let apple_music = track("8F3A1C2B9D0E4F51", "Daft Punk", "Get Lucky");
+        let mpris = track("Daft Punk:Get Lucky", "daft punk", "Get  Lucky ");
+        let windows = track(
+            "Daft Punk:Get Lucky:Random Access Memories",
+            "DAFT PUNK",
+            "get lucky",
+        );
+
+        assert_ne!(apple_music.id, mpris.id);
+        assert_eq!(apple_music.fingerprint(), mpris.fingerprint());
+        assert_eq!(apple_music.fingerprint(), windows.fingerprint());
+        assert_ne!(
+            apple_music.fingerprint(),
+            track("x", "Daft Punk", "Lose Yourself to Dance").fingerprint()
+        );
+    }
+
+    #[test]
+    fn fingerprint_identity_ignores_provider_handover() {
+        let handover = |identity| {
+            let mut config = cfg();
+            config.track_identity = identity;
+            let mut engine = EventEngine::new(config);
+            let now = Instant::now();
+            let _ = engine.tick(snapshot("8F3A1C2B9D0E4F51", true), now, SystemTime::now());
+            engine
+                .tick(
+                    snapshot("Artist:Title", true),
+                    now + Duration::from_secs(1),
+                    SystemTime::now(),
+                )
+                .diff
+        };
+
+        assert_eq!(handover(TrackIdentity::Id), DiffKind::TrackChanged);
+        assert_eq!(handover(TrackIdentity::Fingerprint), DiffKind::Nothing);
+    }
+
     #[test]
     fn debounces_rapid_play_pause_jitter() {
         let mut engine = EventEngine::new(cfg());
@@ -421,8 +1091,8 @@ mod tests {
 
         let second = engine.tick(
             snapshot("1", true),
-            now + Duration::from_secs(16),
-            SystemTime::UNIX_EPOCH + Duration::from_secs(116),
+            now + Duration::from_secs(301),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(401),
         );
 
         let second_ts = match second.action {
@@ -432,4 +1102,1122 @@ mod tests {
 
         assert_eq!(first_ts, second_ts);
     }
+
+    #[test]
+    fn seeks_move_the_start_only_for_providers_that_report_them() {
+        let live = ProviderCapabilities {
+            accurate_position: true,
+            ..ProviderCapabilities::default()
+        };
+        for (capabilities, expected) in [(ProviderCapabilities::default(), 80), (live, 15)] {
+            let mut engine = EventEngine::new(cfg());
+            let now = Instant::now();
+            let mut first = snapshot("1", true);
+            first.capabilities = capabilities;
+            engine.tick(
+                first,
+                now,
+                SystemTime::UNIX_EPOCH + Duration::from_secs(100),
+            );
+
+            let mut seeked = snapshot("1", true);
+            seeked.capabilities = capabilities;
+            seeked.track.as_mut().unwrap().position_ms = Some(90_000);
+            engine.tick(
+                seeked,
+                now + Duration::from_secs(5),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(105),
+            );
+            assert_eq!(engine.stable_start_timestamp, Some(expected));
+        }
+    }
+
+    #[test]
+    fn snaps_jittery_start_timestamps() {
+        let mut track = snapshot("1", true).track.unwrap();
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        track.position_ms = Some(20_400);
+        let start = compute_start_timestamp(&track, at(1_000), None);
+        assert_eq!(start, Some(980));
+
+        for (now, pos_ms) in [(1_001, 21_600), (1_002, 20_900), (1_005, 23_700)] {
+            track.position_ms = Some(pos_ms);
+            assert_eq!(compute_start_timestamp(&track, at(now), start), start);
+        }
+
+        track.position_ms = Some(5_000);
+        assert_eq!(
+            compute_start_timestamp(&track, at(1_010), start),
+            Some(1_005)
+        );
+    }
+
+    #[test]
+    fn keeps_start_when_clock_jumps_backwards() {
+        let mut track = snapshot("1", true).track.unwrap();
+        track.duration_ms = Some(200_000);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        track.position_ms = Some(60_000);
+        let start = compute_start_timestamp(&track, at(10_000), None);
+        assert_eq!(start, Some(9_940));
+
+        // The clock stepped back an hour between pause and resume.
+        track.position_ms = Some(70_000);
+        assert_eq!(compute_start_timestamp(&track, at(6_400), start), start);
+
+        // Seeking forward within the track still moves the start.
+        track.position_ms = Some(190_000);
+        assert_eq!(
+            compute_start_timestamp(&track, at(10_010), start),
+            Some(9_820)
+        );
+
+        // A long pause moves the start later, which is not an anomaly.
+        track.position_ms = Some(70_000);
+        assert_eq!(
+            compute_start_timestamp(&track, at(13_600), start),
+            Some(13_530)
+        );
+    }
+
+    #[test]
+    fn position_granularity_does_not_change_start_timestamp() {
+        let mut track = snapshot("1", true).track.unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        let mut starts = Vec::new();
+        for pos_ms in [41_500, 41_999, 42_000, 42_001, 42_499] {
+            track.position_ms = Some(pos_ms);
+            starts.push(compute_start_timestamp(&track, now, None));
+        }
+        assert!(starts.iter().all(|s| *s == Some(958)), "{starts:?}");
+
+        // Half a second or more rounds up, as a player's own clock display would.
+        track.position_ms = Some(42_500);
+        assert_eq!(compute_start_timestamp(&track, now, None), Some(957));
+    }
+
+    #[test]
+    fn position_past_duration_is_clamped() {
+        let mut track = snapshot("1", true).track.unwrap();
+        track.duration_ms = Some(120_000);
+        track.position_ms = Some(121_500);
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let start = compute_start_timestamp(&track, now, None).unwrap();
+        assert_eq!(start, 880);
+        assert!(start + 120 <= 1_000);
+    }
+
+    #[test]
+    fn configured_video_player_is_watching() {
+        let mut config = cfg();
+        config.player_activity = vec![("vlc".to_string(), ActivityKind::Watching)];
+        let mut engine = EventEngine::new(config);
+
+        let mut snap = snapshot("1", true);
+        snap.track.as_mut().unwrap().player = Some("org.mpris.MediaPlayer2.VLC".to_string());
+        let presence = match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+            EngineAction::Send(p) => p,
+            other => panic!("expected send, got {other:?}"),
+        };
+        assert_eq!(presence.activity_type, 3);
+        assert_eq!(presence.name, "Watching");
+
+        let mut other = snapshot("2", true);
+        other.track.as_mut().unwrap().player = Some("org.mpris.MediaPlayer2.spotify".to_string());
+        let mut engine = EventEngine::new(cfg());
+        match engine.tick(other, Instant::now(), SystemTime::now()).action {
+            EngineAction::Send(p) => assert_eq!(p.activity_type, 2),
+            other => panic!("expected send, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn costly_provider_polls_less_often() {
+        let mut config = cfg();
+        config.provider_poll_floor = vec![("apple_music".to_string(), Duration::from_secs(3))];
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+
+        let mut costly = snapshot("1", true);
+        costly.provider_name = "apple_music";
+        let out = engine.tick(costly, now, SystemTime::now());
+        assert_eq!(out.next_poll_in, Duration::from_secs(3));
+
+        let cheap = engine.tick(snapshot("1", true), now, SystemTime::now());
+        assert_eq!(cheap.next_poll_in, Duration::from_secs(1));
+
+        // The floor never shortens an already longer state-based interval.
+        let mut paused = snapshot("1", false);
+        paused.provider_name = "apple_music";
+        let out = engine.tick(paused, now + Duration::from_secs(1), SystemTime::now());
+        assert_eq!(out.next_poll_in, engine.cfg.paused_poll);
+    }
+
+    #[test]
+    fn engine_output_round_trips_through_json() {
+        let mut engine = EventEngine::new(cfg());
+        let out = engine.tick(snapshot("1", true), Instant::now(), SystemTime::now());
+
+        let json = serde_json::to_value(&out).expect("serialize");
+        assert_eq!(json["next_poll_ms"], 1_000);
+        assert_eq!(json["diff"], "TrackChanged");
+        assert_eq!(json["action"]["Send"]["details"], "Artist — Title");
+
+        let back: EngineOutput = serde_json::from_value(json).expect("deserialize");
+        assert_eq!(back.next_poll_in, out.next_poll_in);
+        assert_eq!(back.reason, out.reason);
+        assert!(matches!(back.action, EngineAction::Send(p) if p.details == "Artist — Title"));
+    }
+
+    #[test]
+    fn empty_artist_has_no_stray_separator() {
+        let mut engine = EventEngine::new(cfg());
+        let mut snap = snapshot("1", true);
+        snap.track.as_mut().unwrap().artist = String::new();
+
+        match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+            EngineAction::Send(p) => {
+                assert_eq!(p.details, "Title");
+                assert_eq!(p.state, "on Album");
+            }
+            other => panic!("expected send, got {other:?}"),
+        }
+
+        let mut config = cfg();
+        config.state_template = Some("{album} ({year})".to_string());
+        let engine = EventEngine::new(config);
+        let mut track = snapshot("1", true).track.unwrap();
+        track.artist.clear();
+        track.year = Some(2013);
+        assert_eq!(engine.to_presence(&track).state, "Album (2013)");
+    }
+
+    #[test]
+    fn activity_name_follows_source_when_enabled() {
+        let name = |source, from_source| {
+            let mut config = cfg();
+            config.name_from_source = from_source;
+            let mut engine = EventEngine::new(config);
+            let mut snap = snapshot("1", true);
+            snap.track.as_mut().unwrap().source = source;
+            match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+                EngineAction::Send(p) => p.name,
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+
+        assert_eq!(name(SourceApp::AppleMusicMac, true), "Apple Music");
+        assert_eq!(name(SourceApp::WindowsMediaSession, true), "Windows Media");
+        assert_eq!(name(SourceApp::Mpris, true), "Media Player");
+        assert_eq!(name(SourceApp::Mpd, true), "MPD");
+        assert_eq!(name(SourceApp::File, true), "Now Playing");
+        assert_eq!(name(SourceApp::Unknown, true), "Music");
+        assert_eq!(name(SourceApp::AppleMusicMac, false), "Listening");
+    }
+
+    #[test]
+    fn compact_mode_keeps_details_and_timestamp_only() {
+        let mut engine = EventEngine::new(EngineConfig {
+            compact: true,
+            party_size: Some(1),
+            ..cfg()
+        });
+        let out = engine.tick(snapshot("1", true), Instant::now(), SystemTime::now());
+        let EngineAction::Send(p) = out.action else {
+            panic!("expected send, got {:?}", out.action);
+        };
+        assert_eq!(p.details, "Artist — Title");
+        assert_eq!(p.state, "");
+        assert!(p.start_timestamp.is_some());
+        assert_eq!((p.large_image, p.large_text), (None, None));
+        assert_eq!((p.small_image, p.small_text), (None, None));
+        assert!(p.buttons.is_empty());
+        assert_eq!(p.party_size, None);
+    }
+
+    #[test]
+    fn small_text_names_the_source_when_enabled() {
+        let small_text = |config: EngineConfig, playing: bool| {
+            let mut engine = EventEngine::new(config);
+            let mut snap = snapshot("1", playing);
+            snap.track.as_mut().unwrap().source = SourceApp::AppleMusicMac;
+            match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+                EngineAction::Send(p) => p.small_text,
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+        let with_source = EngineConfig {
+            small_text_source: true,
+            ..cfg()
+        };
+
+        assert_eq!(
+            small_text(with_source.clone(), true).as_deref(),
+            Some("Playing · Apple Music")
+        );
+        assert_eq!(
+            small_text(with_source.clone(), false).as_deref(),
+            Some("Paused · Apple Music")
+        );
+        let untitled = EngineConfig {
+            small_play_text: None,
+            ..with_source
+        };
+        assert_eq!(small_text(untitled, true).as_deref(), Some("Apple Music"));
+        assert_eq!(small_text(cfg(), true).as_deref(), Some("Playing"));
+    }
+
+    #[test]
+    fn podcast_shows_episode_over_show() {
+        let mut engine = EventEngine::new(cfg());
+        let mut snap = snapshot("ep-42", true);
+        let track = snap.track.as_mut().unwrap();
+        track.is_podcast = true;
+        track.title = "Episode 42: Lifetimes".to_string();
+        track.album = Some("Rustacean Station".to_string());
+
+        match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+            EngineAction::Send(p) => {
+                assert_eq!(p.name, "Podcast");
+                assert_eq!(p.details, "Episode 42: Lifetimes");
+                assert_eq!(p.state, "Rustacean Station");
+            }
+            other => panic!("expected send, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn paused_large_asset_replaces_the_playing_one() {
+        let large = |config: EngineConfig, playing: bool| {
+            let mut engine = EventEngine::new(config);
+            match engine
+                .tick(snapshot("1", playing), Instant::now(), SystemTime::now())
+                .action
+            {
+                EngineAction::Send(p) => (p.large_image, p.large_text),
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+        let paused_assets = EngineConfig {
+            large_image_paused: Some("app_icon_dim".to_string()),
+            large_text_paused: Some("on a break".to_string()),
+            ..cfg()
+        };
+        let playing = (
+            Some("app_icon".to_string()),
+            Some("presence-bridge".to_string()),
+        );
+
+        assert_eq!(large(paused_assets.clone(), true), playing);
+        assert_eq!(
+            large(paused_assets, false),
+            (
+                Some("app_icon_dim".to_string()),
+                Some("on a break".to_string())
+            )
+        );
+        assert_eq!(large(cfg(), false), playing);
+    }
+
+    #[test]
+    fn rotating_large_image_is_stable_per_track() {
+        let mut config = cfg();
+        config.large_image = Some(AssetChoice::Rotate(
+            ["cover_a", "cover_b", "cover_c"].map(String::from).to_vec(),
+        ));
+        let image_for = |id: &str| {
+            let mut engine = EventEngine::new(config.clone());
+            match engine
+                .tick(snapshot(id, true), Instant::now(), SystemTime::now())
+                .action
+            {
+                EngineAction::Send(p) => p.large_image.unwrap(),
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+
+        assert_eq!(image_for("1"), image_for("1"));
+        let images: std::collections::HashSet<_> =
+            (0..20).map(|i| image_for(&i.to_string())).collect();
+        assert!(images.len() > 1, "{images:?}");
+    }
+
+    #[test]
+    fn formats_clock_times() {
+        assert_eq!(format_clock(0), "0:00");
+        assert_eq!(format_clock(999), "0:00");
+        assert_eq!(format_clock(83_000), "1:23");
+        assert_eq!(format_clock(600_000), "10:00");
+        assert_eq!(format_clock(3_599_999), "59:59");
+        assert_eq!(format_clock(3_600_000), "1:00:00");
+        assert_eq!(format_clock(37_230_000), "10:20:30");
+    }
+
+    #[test]
+    fn paused_state_shows_frozen_position() {
+        let paused_state = |position_ms, duration_ms| {
+            let mut config = cfg();
+            config.show_position_when_paused = true;
+            let mut engine = EventEngine::new(config);
+            let mut snap = snapshot("1", false);
+            let track = snap.track.as_mut().unwrap();
+            track.position_ms = position_ms;
+            track.duration_ms = duration_ms;
+            match engine.tick(snap, Instant::now(), SystemTime::now()).action {
+                EngineAction::Send(p) => p.state,
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            paused_state(Some(83_000), Some(225_000)),
+            "Paused · 1:23 / 3:45"
+        );
+        assert_eq!(paused_state(Some(83_000), None), "Paused · 1:23");
+        assert_eq!(
+            paused_state(Some(4_000_000), Some(5_400_000)),
+            "Paused · 1:06:40 / 1:30:00"
+        );
+        assert_eq!(paused_state(None, Some(225_000)), "Paused");
+    }
+
+    #[test]
+    fn presence_layout_presets() {
+        let lines = |layout, playing| {
+            let mut config = cfg();
+            config.presence_layout = layout;
+            let mut engine = EventEngine::new(config);
+            match engine
+                .tick(snapshot("1", playing), Instant::now(), SystemTime::now())
+                .action
+            {
+                EngineAction::Send(p) => (p.details, p.state),
+                other => panic!("expected send, got {other:?}"),
+            }
+        };
+        let owned = |d: &str, s: &str| (d.to_string(), s.to_string());
+
+        assert_eq!(
+            lines(PresenceLayout::ArtistTitle, true),
+            owned("Artist — Title", "on Album")
+        );
+        assert_eq!(
+            lines(PresenceLayout::TitleArtist, true),
+            owned("Title", "Artist")
+        );
+        assert_eq!(
+            lines(PresenceLayout::TitleOnly, true),
+            owned("Title", "on Album")
+        );
+        assert_eq!(
+            lines(PresenceLayout::TitleArtist, false),
+            owned("Title", "Paused")
+        );
+    }
+
+    #[test]
+    fn brief_pause_keeps_start_timestamp() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let start_of = |out: EngineOutput| match out.action {
+            EngineAction::Send(p) => p.start_timestamp,
+            _ => None,
+        };
+
+        let first = start_of(engine.tick(snapshot("1", true), now, at(100)));
+        let _ = engine.tick(snapshot("1", false), now + Duration::from_secs(2), at(102));
+        let mut resumed = snapshot("1", true);
+        resumed.track.as_mut().unwrap().position_ms = Some(21_300);
+        let second = start_of(engine.tick(resumed, now + Duration::from_secs(3), at(103)));
+
+        assert_eq!(first, Some(80));
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn session_timestamp_survives_track_changes_until_stop() {
+        let mut config = cfg();
+        config.timestamp_mode = TimestampMode::Session;
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+        let later = |secs| now + Duration::from_secs(secs);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let start_of = |out: EngineOutput| match out.action {
+            EngineAction::Send(p) => p.start_timestamp,
+            _ => None,
+        };
+
+        assert_eq!(
+            start_of(engine.tick(snapshot("1", true), now, at(100))),
+            Some(80)
+        );
+        assert_eq!(
+            start_of(engine.tick(snapshot("2", true), later(200), at(300))),
+            Some(80)
+        );
+        let _ = engine.tick(snapshot("2", false), later(260), at(360));
+        assert_eq!(
+            start_of(engine.tick(snapshot("3", true), later(400), at(500))),
+            Some(80)
+        );
+
+        let _ = engine.tick(stopped(), later(460), at(560));
+        assert_eq!(
+            start_of(engine.tick(snapshot("4", true), later(600), at(700))),
+            Some(680)
+        );
+    }
+
+    #[test]
+    fn switching_timestamp_mode_rederives_the_start() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+        let later = |secs| now + Duration::from_secs(secs);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let start_of = |out: EngineOutput| match out.action {
+            EngineAction::Send(p) => p.start_timestamp,
+            _ => None,
+        };
+
+        let _ = engine.tick(snapshot("1", true), now, at(100));
+        let mut second = snapshot("2", true);
+        second.track.as_mut().unwrap().position_ms = Some(0);
+        assert_eq!(
+            start_of(engine.tick(second, later(200), at(300))),
+            Some(300)
+        );
+
+        let mut session = cfg();
+        session.timestamp_mode = TimestampMode::Session;
+        engine.update_config(session.clone());
+        let mut third = snapshot("3", true);
+        third.track.as_mut().unwrap().position_ms = Some(10_000);
+        assert_eq!(start_of(engine.tick(third, later(250), at(350))), Some(340));
+
+        // A round trip through per-track must not bring the old session start back.
+        engine.update_config(cfg());
+        let mut same = snapshot("3", true);
+        same.track.as_mut().unwrap().position_ms = Some(20_000);
+        assert_eq!(start_of(engine.tick(same, later(260), at(360))), Some(340));
+        engine.update_config(session);
+        let mut fourth = snapshot("4", true);
+        fourth.track.as_mut().unwrap().position_ms = Some(5_000);
+        assert_eq!(
+            start_of(engine.tick(fourth, later(300), at(400))),
+            Some(395)
+        );
+    }
+
+    #[test]
+    fn sends_idle_presence_on_stop_and_clears_on_shutdown() {
+        let mut config = cfg();
+        config.idle_presence = Some(IdlePresenceConfig {
+            enabled: true,
+            details: "Not listening to anything".to_string(),
+            state: None,
+        });
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let stop = engine.tick(stopped(), now + Duration::from_secs(2), SystemTime::now());
+        assert_eq!(stop.reason, DecisionReason::IdlePresence);
+        match stop.action {
+            EngineAction::Send(p) => {
+                assert_eq!(p.details, "Not listening to anything");
+                assert_eq!(p.start_timestamp, None);
+                assert!(p.buttons.is_empty());
+            }
+            other => panic!("expected idle presence, got {other:?}"),
+        }
+
+        let again = engine.tick(stopped(), now + Duration::from_secs(40), SystemTime::now());
+        assert!(matches!(again.action, EngineAction::None));
+
+        assert!(matches!(engine.shutdown(), EngineAction::Clear));
+        assert!(matches!(engine.shutdown(), EngineAction::None));
+    }
+
+    #[test]
+    fn short_track_played_past_floor_is_sent_once() {
+        let mut config = cfg();
+        config.min_track_duration = Duration::from_secs(30);
+        config.min_visible = Duration::from_secs(5);
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+        let interlude = |id: &str| {
+            let mut snap = snapshot(id, true);
+            snap.track.as_mut().unwrap().duration_ms = Some(20_000);
+            snap
+        };
+
+        // Skipped after two seconds: never shown.
+        let skipped_sends = (0..3)
+            .map(|i| {
+                engine.tick(
+                    interlude("skipped"),
+                    now + Duration::from_secs(i),
+                    SystemTime::now(),
+                )
+            })
+            .filter(|out| matches!(out.action, EngineAction::Send(_)))
+            .count();
+        assert_eq!(skipped_sends, 0);
+
+        let outs: Vec<_> = (3..20)
+            .map(|i| {
+                engine.tick(
+                    interlude("interlude"),
+                    now + Duration::from_secs(i),
+                    SystemTime::now(),
+                )
+            })
+            .collect();
+        let sent_at: Vec<usize> = outs
+            .iter()
+            .enumerate()
+            .filter(|(_, out)| matches!(out.action, EngineAction::Send(_)))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(sent_at, [5]);
+        assert_eq!(outs[5].diff, DiffKind::TrackChanged);
+    }
+
+    #[test]
+    fn ignores_tracks_shorter_than_minimum_duration() {
+        let mut config = cfg();
+        config.min_track_duration = Duration::from_secs(30);
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+
+        let mut clip = snapshot("clip", true);
+        clip.track.as_mut().unwrap().duration_ms = Some(4_000);
+        let out = engine.tick(clip, now, SystemTime::now());
+        assert_eq!(out.diff, DiffKind::Nothing);
+        assert!(matches!(out.action, EngineAction::None));
+
+        let mut unknown = snapshot("stream", true);
+        unknown.track.as_mut().unwrap().duration_ms = None;
+        let out = engine.tick(unknown, now + Duration::from_secs(1), SystemTime::now());
+        assert_eq!(out.diff, DiffKind::TrackChanged);
+
+        let out = engine.tick(
+            snapshot("song", true),
+            now + Duration::from_secs(2),
+            SystemTime::now(),
+        );
+        assert_eq!(out.diff, DiffKind::TrackChanged);
+        assert!(matches!(out.action, EngineAction::Send(_)));
+    }
+
+    #[test]
+    fn renders_state_template_with_track_details() {
+        let mut config = cfg();
+        config.state_template = Some("{album} ({year}) · #{track_number}".to_string());
+        let engine = EventEngine::new(config);
+
+        let mut track = snapshot("1", true).track.unwrap();
+        track.track_number = Some(3);
+        assert_eq!(engine.to_presence(&track).state, "Album · #3");
+
+        track.is_playing = false;
+        assert_eq!(engine.to_presence(&track).state, "Paused");
+    }
+
+    fn stopped() -> ProviderSnapshot {
+        ProviderSnapshot {
+            provider_name: "test",
+            state: PlaybackState::Stopped,
+            track: None,
+            raw_state: None,
+            last_error: None,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    #[test]
+    fn reports_reason_for_each_decision() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let idle = engine.tick(stopped(), now, SystemTime::now());
+        assert_eq!(idle.reason, DecisionReason::NoTrack);
+
+        let first = engine.tick(snapshot("1", true), now, SystemTime::now());
+        assert_eq!(first.reason, DecisionReason::ImmediateChange);
+
+        let debounced = engine.tick(
+            snapshot("1", false),
+            now + Duration::from_millis(100),
+            SystemTime::now(),
+        );
+        assert_eq!(debounced.reason, DecisionReason::Debounced);
+
+        let unchanged = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert_eq!(unchanged.reason, DecisionReason::HashUnchanged);
+
+        let keepalive = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(301),
+            SystemTime::now(),
+        );
+        assert_eq!(keepalive.reason, DecisionReason::Keepalive);
+        assert!(matches!(keepalive.action, EngineAction::Send(_)));
+
+        let cleared = engine.tick(stopped(), now + Duration::from_secs(302), SystemTime::now());
+        assert_eq!(cleared.reason, DecisionReason::Cleared);
+        assert!(matches!(cleared.action, EngineAction::Clear));
+    }
+
+    #[test]
+    fn reports_throttled_when_presence_changes_before_keepalive() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", false), now, SystemTime::now());
+        let mut retagged = snapshot("1", false);
+        retagged.track.as_mut().unwrap().title = "Other Title".to_string();
+
+        let out = engine.tick(retagged, now + Duration::from_secs(1), SystemTime::now());
+        assert_eq!(out.reason, DecisionReason::Throttled);
+        assert!(matches!(out.action, EngineAction::None));
+    }
+
+    #[test]
+    fn paused_reload_with_changed_content_is_sent() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", false), now, SystemTime::now());
+        let mut config = cfg();
+        config.small_pause_image = Some("other".to_string());
+        engine.update_config(config);
+
+        let out = engine.tick(
+            snapshot("1", false),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert_eq!(out.reason, DecisionReason::ContentChanged);
+        match out.action {
+            EngineAction::Send(p) => assert_eq!(p.small_image.as_deref(), Some("other")),
+            other => panic!("expected send, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn timestamp_wobble_resends_only_when_hashed() {
+        let sends_after_wobble = |hash_start_timestamp| {
+            let mut config = cfg();
+            config.hash_start_timestamp = hash_start_timestamp;
+            let mut engine = EventEngine::new(config);
+            let now = Instant::now();
+            let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+
+            (1..=3)
+                .filter(|&step| {
+                    engine.stable_start_timestamp =
+                        engine.stable_start_timestamp.map(|start| start + 1);
+                    let out = engine.tick(
+                        snapshot("1", true),
+                        now + Duration::from_secs(20 * step),
+                        SystemTime::now(),
+                    );
+                    matches!(out.action, EngineAction::Send(_))
+                })
+                .count()
+        };
+
+        assert_eq!(sends_after_wobble(true), 3);
+        assert_eq!(sends_after_wobble(false), 0);
+    }
+
+    #[test]
+    fn resends_content_change_after_min_interval() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let mut config = cfg();
+        config.small_play_image = Some("other".to_string());
+        engine.update_config(config);
+
+        // A reload goes out on the next tick.
+        let reloaded = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert_eq!(reloaded.reason, DecisionReason::ContentChanged);
+
+        // Other content changes wait out the minimum interval.
+        let retagged = || {
+            let mut snap = snapshot("1", true);
+            snap.track.as_mut().unwrap().album = Some("Other Album".to_string());
+            snap
+        };
+        let early = engine.tick(retagged(), now + Duration::from_secs(2), SystemTime::now());
+        assert_eq!(early.reason, DecisionReason::Throttled);
+
+        let due = engine.tick(retagged(), now + Duration::from_secs(17), SystemTime::now());
+        assert_eq!(due.reason, DecisionReason::ContentChanged);
+        assert!(matches!(due.action, EngineAction::Send(_)));
+    }
+
+    #[test]
+    fn short_gap_within_clear_grace_does_not_clear() {
+        let mut config = cfg();
+        config.clear_grace = Duration::from_secs(3);
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let gap = engine.tick(stopped(), now + Duration::from_secs(1), SystemTime::now());
+        assert_eq!(gap.reason, DecisionReason::ClearDeferred);
+        assert!(matches!(gap.action, EngineAction::None));
+        assert!(gap.next_poll_in <= Duration::from_secs(3));
+
+        let next = engine.tick(
+            snapshot("2", true),
+            now + Duration::from_secs(2),
+            SystemTime::now(),
+        );
+        assert_eq!(next.diff, DiffKind::TrackChanged);
+        assert!(matches!(next.action, EngineAction::Send(_)));
+    }
+
+    #[test]
+    fn stop_outlasting_clear_grace_clears() {
+        let mut config = cfg();
+        config.clear_grace = Duration::from_secs(3);
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let first = engine.tick(stopped(), now + Duration::from_secs(1), SystemTime::now());
+        let waiting = engine.tick(stopped(), now + Duration::from_secs(3), SystemTime::now());
+        assert_eq!(first.reason, DecisionReason::ClearDeferred);
+        assert_eq!(waiting.reason, DecisionReason::ClearDeferred);
+        assert_eq!(waiting.next_poll_in, Duration::from_secs(1));
+
+        let cleared = engine.tick(stopped(), now + Duration::from_secs(4), SystemTime::now());
+        assert_eq!(cleared.reason, DecisionReason::Cleared);
+        assert!(matches!(cleared.action, EngineAction::Clear));
+    }
+
+    #[test]
+    fn adaptive_poll_speeds_up_after_change_and_backs_off() {
+        let mut config = cfg();
+        config.adaptive_poll_min = Duration::from_millis(250);
+        let mut engine = EventEngine::new(config);
+        let now = Instant::now();
+        let mut poll = |id: &str, playing: bool, secs: u64| {
+            engine
+                .tick(
+                    snapshot(id, playing),
+                    now + Duration::from_secs(secs),
+                    SystemTime::now(),
+                )
+                .next_poll_in
+                .as_millis()
+        };
+
+        assert_eq!(poll("1", true, 0), 250);
+        assert_eq!(poll("1", true, 1), 500);
+        assert_eq!(poll("1", true, 2), 1_000);
+        assert_eq!(poll("1", true, 3), 1_000);
+        assert_eq!(poll("2", true, 4), 250);
+        assert_eq!(poll("2", true, 5), 500);
+    }
+
+    #[test]
+    fn skips_resends_during_steady_playback() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let sends = (0..300)
+            .map(|sec| {
+                engine.tick(
+                    snapshot("1", true),
+                    now + Duration::from_secs(sec),
+                    SystemTime::now(),
+                )
+            })
+            .filter(|out| matches!(out.action, EngineAction::Send(_)))
+            .count();
+        assert_eq!(sends, 1);
+
+        let safety = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(300),
+            SystemTime::now(),
+        );
+        assert!(matches!(safety.action, EngineAction::Send(_)));
+    }
+
+    #[test]
+    fn omits_timestamp_when_disabled() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let first = engine.tick(snapshot("1", true), now, SystemTime::now());
+        assert!(matches!(first.action, EngineAction::Send(ref p) if p.start_timestamp.is_some()));
+
+        let mut disabled = cfg();
+        disabled.show_timestamp = false;
+        engine.update_config(disabled);
+
+        let second = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert!(matches!(second.action, EngineAction::Send(ref p) if p.start_timestamp.is_none()));
+    }
+
+    #[test]
+    fn truncates_long_multibyte_text_on_char_boundary() {
+        let long = "ñ€😀".repeat(40);
+        let out = truncate_text(long, DISCORD_TEXT_LIMIT);
+        assert!(out.len() <= DISCORD_TEXT_LIMIT);
+        assert!(out.ends_with('…'));
+
+        let short = truncate_text("Artist — Title".to_string(), DISCORD_TEXT_LIMIT);
+        assert_eq!(short, "Artist — Title");
+    }
+
+    #[test]
+    fn button_labels_take_prefix_and_fit_discord_limit() {
+        let mut engine = EventEngine::new(EngineConfig {
+            button_prefix: Some("🎧🎶🎵 Ascolta su ".to_string()),
+            ..cfg()
+        });
+        let out = engine.tick(snapshot("1", true), Instant::now(), SystemTime::now());
+        let EngineAction::Send(presence) = out.action else {
+            panic!("expected a send, got {:?}", out.action);
+        };
+        let labels: Vec<_> = presence.buttons.iter().map(|b| b.label.as_str()).collect();
+        // Multi-byte characters count once, so the prefix doesn't eat the label.
+        assert_eq!(
+            labels,
+            [
+                "🎧🎶🎵 Ascolta su Open/Search Appl…",
+                "🎧🎶🎵 Ascolta su Search Spotify"
+            ]
+        );
+        assert!(labels
+            .iter()
+            .all(|l| l.chars().count() <= DISCORD_BUTTON_LABEL_LIMIT));
+    }
+
+    #[test]
+    fn clean_search_mode_rebuilds_button_links() {
+        let mut engine = EventEngine::new(EngineConfig {
+            search_query: SearchQueryMode::Clean,
+            ..cfg()
+        });
+        let mut snap = snapshot("1", true);
+        if let Some(track) = snap.track.as_mut() {
+            track.title = "Title (feat. Guest)".to_string();
+            track.links.apple_music = None;
+        }
+        let out = engine.tick(snap, Instant::now(), SystemTime::now());
+        let EngineAction::Send(presence) = out.action else {
+            panic!("expected a send, got {:?}", out.action);
+        };
+        let urls: Vec<_> = presence.buttons.iter().map(|b| b.url.as_str()).collect();
+        assert_eq!(urls, ["https://open.spotify.com/search/Artist%20Title"]);
+    }
+
+    #[test]
+    fn presence_details_fit_discord_limit() {
+        let mut engine = EventEngine::new(cfg());
+        let mut snap = snapshot("1", true);
+        if let Some(track) = snap.track.as_mut() {
+            track.title = "日本語のとても長いタイトル".repeat(10);
+        }
+
+        let out = engine.tick(snap, Instant::now(), SystemTime::now());
+        match out.action {
+            EngineAction::Send(p) => {
+                assert!(p.details.len() <= DISCORD_TEXT_LIMIT);
+                assert!(p.details.starts_with("Artist — 日本語"));
+            }
+            _ => panic!("expected send"),
+        }
+    }
+
+    #[test]
+    fn clears_once_while_stopped_and_error_snapshots_flap() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let clears = (1..=6)
+            .map(|i| {
+                let snap = if i % 2 == 0 {
+                    ProviderSnapshot::with_error(
+                        "test",
+                        ProviderError::Transport("dbus hiccup".into()),
+                    )
+                } else {
+                    stopped()
+                };
+                engine.tick(snap, now + Duration::from_secs(i), SystemTime::now())
+            })
+            .filter(|out| matches!(out.action, EngineAction::Clear))
+            .count();
+        assert_eq!(clears, 1);
+    }
+
+    #[test]
+    fn holds_presence_through_transient_provider_error() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let held = engine.tick(
+            ProviderSnapshot::with_error("test", ProviderError::Transport("dbus hiccup".into())),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert_eq!(held.reason, DecisionReason::ProviderErrorHold);
+        assert!(matches!(held.action, EngineAction::None));
+        assert_eq!(held.next_poll_in, Duration::from_secs(1));
+
+        let recovered = engine.tick(
+            snapshot("1", true),
+            now + Duration::from_secs(2),
+            SystemTime::now(),
+        );
+        assert!(!matches!(recovered.action, EngineAction::Clear));
+
+        let _ = engine.tick(
+            ProviderSnapshot::with_error("test", ProviderError::Transport("dbus hiccup".into())),
+            now + Duration::from_secs(3),
+            SystemTime::now(),
+        );
+        let expired = engine.tick(
+            ProviderSnapshot::with_error("test", ProviderError::Transport("dbus hiccup".into())),
+            now + Duration::from_secs(14),
+            SystemTime::now(),
+        );
+        assert!(matches!(expired.action, EngineAction::Clear));
+    }
+
+    #[test]
+    fn permission_denied_clears_without_grace() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let out = engine.tick(
+            ProviderSnapshot::with_error(
+                "test",
+                ProviderError::PermissionDenied("automation".into()),
+            ),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert!(matches!(out.action, EngineAction::Clear));
+    }
+
+    #[test]
+    fn genuine_stop_clears_immediately() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", true), now, SystemTime::now());
+        let out = engine.tick(stopped(), now + Duration::from_secs(1), SystemTime::now());
+        assert!(matches!(out.action, EngineAction::Clear));
+    }
+
+    #[test]
+    fn force_resend_sends_paused_presence() {
+        let mut engine = EventEngine::new(cfg());
+        let now = Instant::now();
+
+        let _ = engine.tick(snapshot("1", false), now, SystemTime::now());
+        engine.force_resend();
+        let forced = engine.tick(
+            snapshot("1", false),
+            now + Duration::from_secs(1),
+            SystemTime::now(),
+        );
+        assert_eq!(forced.reason, DecisionReason::Forced);
+        assert!(matches!(forced.action, EngineAction::Send(_)));
+
+        let after = engine.tick(
+            snapshot("1", false),
+            now + Duration::from_secs(2),
+            SystemTime::now(),
+        );
+        assert!(matches!(after.action, EngineAction::None));
+    }
+
+    #[test]
+    fn track_change_callback_fires_once_per_track() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = EventEngine::new(cfg());
+        let sink = Arc::clone(&seen);
+        engine.set_on_track_change(Box::new(move |track| {
+            sink.lock().unwrap().push(track.id.clone());
+        }));
+
+        let now = Instant::now();
+        for (i, (id, playing)) in [
+            ("1", true),
+            ("1", true),
+            ("1", false),
+            ("2", true),
+            ("2", true),
+            ("1", true),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let _ = engine.tick(
+                snapshot(id, playing),
+                now + Duration::from_millis(100 * i as u64),
+                SystemTime::now(),
+            );
+        }
+
+        assert_eq!(*seen.lock().unwrap(), vec!["1", "2", "1"]);
+    }
+
+    #[test]
+    fn uses_configured_small_text() {
+        let mut cfg = cfg();
+        cfg.small_pause_text = Some("⏸ In pausa".to_string());
+        let mut engine = EventEngine::new(cfg);
+
+        let out = engine.tick(snapshot("1", false), Instant::now(), SystemTime::now());
+        match out.action {
+            EngineAction::Send(p) => assert_eq!(p.small_text.as_deref(), Some("⏸ In pausa")),
+            _ => panic!("expected send"),
+        }
+    }
+
+    #[test]
+    fn formats_state_with_locale() {
+        let mut cfg = cfg();
+        cfg.locale = "it".to_string();
+        let mut engine = EventEngine::new(cfg);
+
+        let out = engine.tick(snapshot("1", true), Instant::now(), SystemTime::now());
+        match out.action {
+            EngineAction::Send(p) => assert_eq!(p.state, "da Album"),
+            _ => panic!("expected send"),
+        }
+    }
 }
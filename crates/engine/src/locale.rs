@@ -0,0 +1,97 @@
+#[derive(Debug)]
+pub struct Strings {
+    pub playing: &'static str,
+    pub paused: &'static str,
+    /// Template for the album line; `{album}` is replaced with the album title.
+    pub on_album: &'static str,
+}
+
+const EN: Strings = Strings {
+    playing: "Playing",
+    paused: "Paused",
+    on_album: "on {album}",
+};
+
+const TABLE: &[(&str, Strings)] = &[
+    ("en", EN),
+    (
+        "it",
+        Strings {
+            playing: "In riproduzione",
+            paused: "In pausa",
+            on_album: "da {album}",
+        },
+    ),
+    (
+        "de",
+        Strings {
+            playing: "Läuft",
+            paused: "Pausiert",
+            on_album: "auf {album}",
+        },
+    ),
+    (
+        "fr",
+        Strings {
+            playing: "En lecture",
+            paused: "En pause",
+            on_album: "sur {album}",
+        },
+    ),
+    (
+        "es",
+        Strings {
+            playing: "Reproduciendo",
+            paused: "En pausa",
+            on_album: "en {album}",
+        },
+    ),
+    (
+        "pt",
+        Strings {
+            playing: "Tocando",
+            paused: "Pausado",
+            on_album: "em {album}",
+        },
+    ),
+];
+
+/// Looks up strings by language, accepting region-qualified tags like `it-IT`.
+pub fn strings_for(locale: &str) -> &'static Strings {
+    let lang = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    TABLE
+        .iter()
+        .find(|(key, _)| *key == lang)
+        .map(|(_, strings)| strings)
+        .unwrap_or(&EN)
+}
+
+impl Strings {
+    pub fn on_album(&self, album: &str) -> String {
+        self.on_album.replace("{album}", album)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strings_for;
+
+    #[test]
+    fn resolves_known_locale() {
+        let it = strings_for("it-IT");
+        assert_eq!(it.paused, "In pausa");
+        assert_eq!(it.on_album("Anime salve"), "da Anime salve");
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        let unknown = strings_for("xx");
+        assert_eq!(unknown.playing, "Playing");
+        assert_eq!(unknown.on_album("Discovery"), "on Discovery");
+        assert_eq!(strings_for("").paused, "Paused");
+    }
+}
@@ -0,0 +1,72 @@
+/// Fills `{name}` placeholders from `fields`. Any whitespace-separated word whose
+/// placeholder has no value is dropped, along with separators left dangling.
+pub fn render(template: &str, fields: &[(&str, Option<String>)]) -> String {
+    let mut words: Vec<String> = Vec::new();
+    'words: for word in template.split_whitespace() {
+        let mut out = word.to_string();
+        for (name, value) in fields {
+            let placeholder = format!("{{{name}}}");
+            if !out.contains(&placeholder) {
+                continue;
+            }
+            match value {
+                Some(v) if !v.is_empty() => out = out.replace(&placeholder, v),
+                _ => continue 'words,
+            }
+        }
+        if is_separator(&out) && words.last().is_none_or(|w| is_separator(w)) {
+            continue;
+        }
+        words.push(out);
+    }
+    while words.last().is_some_and(|w| is_separator(w)) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+fn is_separator(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| matches!(c, '-' | '–' | '—' | '·' | '•' | '|' | ','))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    fn fields(track_number: Option<u32>, year: Option<u32>) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("album", Some("Random Access Memories".to_string())),
+            ("track_number", track_number.map(|n| n.to_string())),
+            ("year", year.map(|y| y.to_string())),
+        ]
+    }
+
+    #[test]
+    fn renders_all_fields() {
+        let out = render(
+            "{album} ({year}) · #{track_number}",
+            &fields(Some(8), Some(2013)),
+        );
+        assert_eq!(out, "Random Access Memories (2013) · #8");
+    }
+
+    #[test]
+    fn collapses_missing_fields() {
+        let template = "{album} ({year}) · #{track_number}";
+        assert_eq!(
+            render(template, &fields(Some(8), None)),
+            "Random Access Memories · #8"
+        );
+        assert_eq!(
+            render(template, &fields(None, Some(2013))),
+            "Random Access Memories (2013)"
+        );
+        assert_eq!(
+            render("#{track_number} · {album}", &fields(None, None)),
+            "Random Access Memories"
+        );
+    }
+}
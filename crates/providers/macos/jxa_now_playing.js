@@ -18,9 +18,13 @@ function run() {
         out.title = track.name();
         out.artist = track.artist();
         out.album = track.album();
+        out.trackNumber = track.trackNumber();
+        out.year = track.year();
         out.duration = Math.round((track.duration() || 0) * 1000);
         out.position = Math.round((music.playerPosition() || 0) * 1000);
         out.persistentId = track.persistentID();
+        out.mediaKind = String(track.mediaKind());
+        out.genre = track.genre();
       }
     }
 
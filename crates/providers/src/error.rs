@@ -0,0 +1,153 @@
+use std::io::ErrorKind;
+
+/// Why a provider couldn't report playback, so callers can tell a lasting condition from a
+/// transient one.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProviderError {
+    /// The player or its service isn't running.
+    #[error("not running: {0}")]
+    NotRunning(String),
+    /// The OS refused access, e.g. macOS Automation permission; retrying won't help.
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    /// The connection to the player (DBus, a socket, a subprocess) failed.
+    #[error("{0}")]
+    Transport(String),
+    /// The player answered with something that couldn't be understood.
+    #[error("unexpected response: {0}")]
+    Parse(String),
+    #[error("timed out: {0}")]
+    Timeout(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ProviderError {
+    /// Errors worth riding out: the next poll may well succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Transport(_) | Self::Parse(_) | Self::Timeout(_) | Self::Other(_)
+        )
+    }
+
+    fn from_io(err: &std::io::Error, message: String) -> Self {
+        match err.kind() {
+            ErrorKind::NotFound | ErrorKind::ConnectionRefused => Self::NotRunning(message),
+            ErrorKind::PermissionDenied => Self::PermissionDenied(message),
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Self::Timeout(message),
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Self::Parse(message),
+            _ => Self::Transport(message),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ProviderError {
+    fn from_fdo(err: &zbus::fdo::Error, message: String) -> Self {
+        use zbus::fdo::Error;
+        match err {
+            Error::ServiceUnknown(_) | Error::NameHasNoOwner(_) => Self::NotRunning(message),
+            Error::AccessDenied(_) => Self::PermissionDenied(message),
+            Error::Timeout(_) | Error::NoReply(_) => Self::Timeout(message),
+            _ => Self::Transport(message),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<zbus::Error> for ProviderError {
+    fn from(err: zbus::Error) -> Self {
+        match err {
+            zbus::Error::FDO(fdo) => (*fdo).into(),
+            other => Self::Transport(other.to_string()),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<zbus::fdo::Error> for ProviderError {
+    fn from(err: zbus::fdo::Error) -> Self {
+        let message = err.to_string();
+        Self::from_fdo(&err, message)
+    }
+}
+
+/// Classifies by the innermost recognisable cause, keeping the full context as the message.
+impl From<anyhow::Error> for ProviderError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = format!("{err:#}");
+        for cause in err.chain() {
+            if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+                return Self::from_io(io, message);
+            }
+            if cause.is::<serde_json::Error>() || cause.is::<std::string::FromUtf8Error>() {
+                return Self::Parse(message);
+            }
+            if cause.is::<tokio::time::error::Elapsed>() {
+                return Self::Timeout(message);
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(fdo) = cause.downcast_ref::<zbus::fdo::Error>() {
+                return Self::from_fdo(fdo, message);
+            }
+            #[cfg(target_os = "linux")]
+            if cause.is::<zbus::Error>() {
+                return Self::Transport(message);
+            }
+        }
+        Self::Other(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProviderError;
+    use anyhow::Context;
+
+    #[test]
+    fn maps_failures_to_variants() {
+        let io = |kind| anyhow::Error::new(std::io::Error::new(kind, "boom"));
+        assert!(matches!(
+            ProviderError::from(io(std::io::ErrorKind::ConnectionRefused)),
+            ProviderError::NotRunning(_)
+        ));
+        assert!(matches!(
+            ProviderError::from(io(std::io::ErrorKind::PermissionDenied)),
+            ProviderError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            ProviderError::from(io(std::io::ErrorKind::BrokenPipe)),
+            ProviderError::Transport(_)
+        ));
+
+        let json = serde_json::from_str::<serde_json::Value>("{")
+            .context("invalid now-playing JSON")
+            .unwrap_err();
+        match ProviderError::from(json) {
+            ProviderError::Parse(message) => {
+                assert!(message.starts_with("invalid now-playing JSON: "))
+            }
+            other => panic!("expected parse error, got {other:?}"),
+        }
+
+        assert!(matches!(
+            ProviderError::from(anyhow::anyhow!("mpd status failed")),
+            ProviderError::Other(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn elapsed_timeout_is_a_timeout() {
+        let elapsed = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            std::future::pending::<()>(),
+        )
+        .await
+        .unwrap_err();
+        let err = ProviderError::from(anyhow::Error::new(elapsed));
+        assert!(matches!(err, ProviderError::Timeout(_)));
+        assert!(err.is_transient());
+        assert!(!ProviderError::PermissionDenied("automation".into()).is_transient());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
+use anyhow::Context;
+use async_trait::async_trait;
+use presence_bridge_core::text::clean_metadata;
+use presence_bridge_core::{
+    clamp_position, urls, PlaybackState, SearchQueryMode, SourceApp, Track,
+};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Reads now-playing data written by an external script to a file or named pipe.
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+/// The file format: one JSON object, e.g.
+/// `{"state":"playing","artist":"A","title":"T","album":"B","duration_ms":215000,"position_ms":42000}`.
+/// `state` is `playing`, `paused` or `stopped`; only `title` is required otherwise.
+#[derive(Debug, Deserialize)]
+struct FileTrack {
+    state: String,
+    id: Option<String>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    year: Option<u32>,
+    duration_ms: Option<u64>,
+    position_ms: Option<u64>,
+}
+
+impl FileProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl NowPlayingProvider for FileProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn source(&self) -> SourceApp {
+        SourceApp::File
+    }
+
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+        if !self.path.exists() {
+            return Ok(ProviderSnapshot::stopped(self.name()));
+        }
+        let data = tokio::time::timeout(READ_TIMEOUT, read_without_blocking(&self.path))
+            .await
+            .map_err(|_| ProviderError::Timeout(format!("reading {}", self.path.display())))?
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        snapshot_from_json(self.name(), &data)
+    }
+}
+
+/// Reads the whole file. Opening a named pipe normally blocks until a writer shows up,
+/// and a timeout can't cancel that `open()`, so on Unix it is opened non-blocking: with
+/// no writer it reads as empty. Elsewhere the caller's timeout is the only bound.
+async fn read_without_blocking(path: &Path) -> std::io::Result<String> {
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+        let mut data = String::new();
+        options.open(&path)?.read_to_string(&mut data)?;
+        Ok(data)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+fn snapshot_from_json(name: &'static str, data: &str) -> Result<ProviderSnapshot, ProviderError> {
+    if data.trim().is_empty() {
+        return Ok(ProviderSnapshot::stopped(name));
+    }
+    let parsed: FileTrack =
+        serde_json::from_str(data.trim()).context("invalid now-playing JSON")?;
+
+    let is_playing = match parsed.state.as_str() {
+        "playing" => true,
+        "paused" => false,
+        "stopped" => return Ok(ProviderSnapshot::stopped(name)),
+        other => {
+            return Err(ProviderError::Parse(format!(
+                "unknown state '{other}' in now-playing JSON"
+            )))
+        }
+    };
+    let Some(title) = parsed
+        .title
+        .map(|t| clean_metadata(&t))
+        .filter(|t| !t.is_empty())
+    else {
+        return Ok(ProviderSnapshot::stopped(name));
+    };
+    let artist = parsed
+        .artist
+        .map(|a| clean_metadata(&a))
+        .filter(|a| !a.is_empty())
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+    let links = urls::track_links(&artist, &title, SearchQueryMode::Raw);
+
+    let track = Track {
+        id: parsed.id.unwrap_or_else(|| format!("{}:{}", artist, title)),
+        title,
+        artist,
+        album: parsed
+            .album
+            .map(|a| clean_metadata(&a))
+            .filter(|a| !a.is_empty()),
+        track_number: parsed.track_number,
+        year: parsed.year,
+        duration_ms: parsed.duration_ms,
+        position_ms: clamp_position(parsed.position_ms, parsed.duration_ms),
+        is_playing,
+        source: SourceApp::File,
+        player: None,
+        links,
+        is_podcast: false,
+        updated_at: SystemTime::now(),
+    };
+
+    Ok(ProviderSnapshot {
+        provider_name: name,
+        state: if is_playing {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Paused
+        },
+        track: Some(track),
+        raw_state: Some(parsed.state),
+        last_error: None,
+        capabilities: ProviderCapabilities::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot_from_json;
+    use super::FileProvider;
+    use crate::ProviderError;
+    use crate::{NowPlayingProvider, ProviderCapabilities};
+    use presence_bridge_core::PlaybackState;
+
+    #[test]
+    fn parses_sample_file() {
+        let data = r#"{
+            "state": "playing",
+            "title": "Get Lucky",
+            "artist": "Daft Punk",
+            "album": "Random Access Memories",
+            "duration_ms": 248000,
+            "position_ms": 60000
+        }"#;
+        let snap = snapshot_from_json("file", data).expect("snapshot");
+        assert_eq!(snap.state, PlaybackState::Playing);
+        let track = snap.track.expect("track");
+        assert_eq!(track.id, "Daft Punk:Get Lucky");
+        assert_eq!(track.album.as_deref(), Some("Random Access Memories"));
+        assert_eq!(track.position_ms, Some(60_000));
+    }
+
+    #[test]
+    fn empty_or_stopped_files_are_stopped() {
+        for data in [
+            "",
+            "  \n",
+            r#"{"state":"stopped"}"#,
+            r#"{"state":"paused"}"#,
+        ] {
+            let snap = snapshot_from_json("file", data).expect("snapshot");
+            assert_eq!(snap.state, PlaybackState::Stopped);
+            assert!(snap.track.is_none());
+        }
+        assert!(matches!(
+            snapshot_from_json("file", r#"{"state":"rewinding"}"#),
+            Err(ProviderError::Parse(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn pipe_without_a_writer_reads_as_stopped() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = std::env::temp_dir().join(format!("pb-fifo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir");
+        let fifo = dir.join("now-playing");
+        let _ = std::fs::remove_file(&fifo);
+        let c_path = CString::new(fifo.as_os_str().as_bytes()).expect("path");
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        let mut provider = FileProvider::new(fifo);
+        for _ in 0..3 {
+            let snap = provider.poll().await.expect("no writer is not an error");
+            assert_eq!(snap.state, PlaybackState::Stopped);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn advertises_nothing() {
+        // Whatever the external script writes is taken at face value.
+        let provider = FileProvider::new("/tmp/now-playing.json".into());
+        assert_eq!(provider.capabilities(), ProviderCapabilities::default());
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait ForegroundSource: Send {
+    /// Identifies the app owning the focused window: its bundle id on macOS, executable
+    /// name on Windows, `WM_CLASS` on X11. `None` when no window has focus.
+    async fn foreground_app(&mut self) -> Result<Option<String>>;
+}
+
+pub fn system_foreground_source() -> Option<Box<dyn ForegroundSource>> {
+    platform::foreground_source()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::ForegroundSource;
+    use anyhow::{anyhow, Context, Result};
+    use async_trait::async_trait;
+    use tokio::process::Command;
+
+    /// Asks the X server through `xprop`; Wayland compositors offer no common equivalent.
+    struct XpropForegroundSource;
+
+    async fn xprop(args: &[&str]) -> Result<String> {
+        let output = Command::new("xprop")
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("failed to run xprop")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "xprop failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    #[async_trait]
+    impl ForegroundSource for XpropForegroundSource {
+        async fn foreground_app(&mut self) -> Result<Option<String>> {
+            let active = xprop(&["-root", "_NET_ACTIVE_WINDOW"]).await?;
+            let Some(window) = active_window_id(&active) else {
+                return Ok(None);
+            };
+            let class = xprop(&["-id", window, "WM_CLASS"]).await?;
+            Ok(wm_class(&class))
+        }
+    }
+
+    /// `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007`; `0x0` means nothing focused.
+    pub(super) fn active_window_id(raw: &str) -> Option<&str> {
+        let id = raw.split_whitespace().last()?;
+        (id.starts_with("0x") && id != "0x0").then_some(id)
+    }
+
+    /// `WM_CLASS(STRING) = "spotify", "Spotify"` becomes `spotify Spotify`.
+    pub(super) fn wm_class(raw: &str) -> Option<String> {
+        let (_, values) = raw.split_once('=')?;
+        let parts: Vec<&str> = values
+            .split(',')
+            .map(|part| part.trim().trim_matches('"'))
+            .filter(|part| !part.is_empty())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+
+    pub fn foreground_source() -> Option<Box<dyn ForegroundSource>> {
+        std::env::var_os("DISPLAY")
+            .is_some()
+            .then(|| Box::new(XpropForegroundSource) as Box<dyn ForegroundSource>)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::ForegroundSource;
+    use anyhow::{anyhow, Context, Result};
+    use async_trait::async_trait;
+    use tokio::process::Command;
+
+    /// Reads `NSWorkspace.frontmostApplication` through the JXA ObjC bridge, which needs
+    /// no Automation permission.
+    const FRONTMOST_SCRIPT: &str = "ObjC.import('AppKit'); \
+        $.NSWorkspace.sharedWorkspace.frontmostApplication.bundleIdentifier.js";
+
+    struct WorkspaceForegroundSource;
+
+    #[async_trait]
+    impl ForegroundSource for WorkspaceForegroundSource {
+        async fn foreground_app(&mut self) -> Result<Option<String>> {
+            let output = Command::new("osascript")
+                .args(["-l", "JavaScript", "-e", FRONTMOST_SCRIPT])
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("failed to run osascript for the frontmost app")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "osascript failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            let bundle = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            Ok((!bundle.is_empty() && bundle != "undefined").then_some(bundle))
+        }
+    }
+
+    pub fn foreground_source() -> Option<Box<dyn ForegroundSource>> {
+        Some(Box::new(WorkspaceForegroundSource))
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::ForegroundSource;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::path::Path;
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    struct ForegroundWindowSource;
+
+    /// The executable name of the process owning the foreground window.
+    fn foreground_exe() -> Result<Option<String>> {
+        unsafe {
+            let hwnd = GetForegroundWindow();
+            if hwnd.is_invalid() {
+                return Ok(None);
+            }
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
+            if pid == 0 {
+                return Ok(None);
+            }
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)?;
+            let mut buf = [0u16; 1024];
+            let mut len = buf.len() as u32;
+            let queried = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buf.as_mut_ptr()),
+                &mut len,
+            );
+            let _ = CloseHandle(process);
+            queried?;
+            let path = String::from_utf16_lossy(&buf[..len as usize]);
+            Ok(Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()))
+        }
+    }
+
+    #[async_trait]
+    impl ForegroundSource for ForegroundWindowSource {
+        async fn foreground_app(&mut self) -> Result<Option<String>> {
+            foreground_exe()
+        }
+    }
+
+    pub fn foreground_source() -> Option<Box<dyn ForegroundSource>> {
+        Some(Box::new(ForegroundWindowSource))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::ForegroundSource;
+
+    pub fn foreground_source() -> Option<Box<dyn ForegroundSource>> {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::platform::{active_window_id, wm_class};
+
+    #[test]
+    fn parses_xprop_output() {
+        assert_eq!(
+            active_window_id("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"),
+            Some("0x3a00007")
+        );
+        assert_eq!(
+            active_window_id("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0"),
+            None
+        );
+        assert_eq!(
+            wm_class("WM_CLASS(STRING) = \"spotify\", \"Spotify\"\n").as_deref(),
+            Some("spotify Spotify")
+        );
+        assert_eq!(wm_class("WM_CLASS:  not found."), None);
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+#[async_trait]
+pub trait IdleSource: Send {
+    /// How long the user has been idle; `Duration::MAX` when the session is locked.
+    async fn idle_time(&mut self) -> Result<Duration>;
+}
+
+pub fn system_idle_source() -> Option<Box<dyn IdleSource>> {
+    platform::idle_source()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::IdleSource;
+    use anyhow::{Context, Result};
+    use async_trait::async_trait;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use zbus::{CacheProperties, Connection, Proxy, ProxyBuilder};
+
+    /// Holds the session proxy across polls; an error drops it so the next poll reconnects.
+    #[derive(Default)]
+    struct LogindIdleSource {
+        session: Option<Proxy<'static>>,
+    }
+
+    async fn connect_session() -> Result<Proxy<'static>> {
+        let conn = Connection::system()
+            .await
+            .context("failed to connect DBus system bus")?;
+        // Read the hints fresh each poll rather than trusting change signals for them.
+        Ok(ProxyBuilder::new(&conn)
+            .destination("org.freedesktop.login1")?
+            .path("/org/freedesktop/login1/session/auto")?
+            .interface("org.freedesktop.login1.Session")?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn read_idle_time(proxy: &Proxy<'static>) -> Result<Duration> {
+        let locked: bool = proxy.get_property("LockedHint").await.unwrap_or(false);
+        if locked {
+            return Ok(Duration::MAX);
+        }
+        let idle: bool = proxy.get_property("IdleHint").await?;
+        if !idle {
+            return Ok(Duration::ZERO);
+        }
+        let since_us: u64 = proxy.get_property("IdleSinceHint").await?;
+        let now_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        Ok(Duration::from_micros(now_us.saturating_sub(since_us)))
+    }
+
+    #[async_trait]
+    impl IdleSource for LogindIdleSource {
+        async fn idle_time(&mut self) -> Result<Duration> {
+            let session = match &self.session {
+                Some(session) => session.clone(),
+                None => self.session.insert(connect_session().await?).clone(),
+            };
+            let idle = read_idle_time(&session).await;
+            if idle.is_err() {
+                self.session = None;
+            }
+            idle
+        }
+    }
+
+    pub fn idle_source() -> Option<Box<dyn IdleSource>> {
+        Some(Box::<LogindIdleSource>::default())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::IdleSource;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    const COMBINED_SESSION_STATE: i32 = 0;
+    const ANY_INPUT_EVENT_TYPE: u32 = u32::MAX;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+    }
+
+    struct CoreGraphicsIdleSource;
+
+    #[async_trait]
+    impl IdleSource for CoreGraphicsIdleSource {
+        async fn idle_time(&mut self) -> Result<Duration> {
+            let secs = unsafe {
+                CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT_TYPE)
+            };
+            Ok(Duration::from_secs_f64(secs.max(0.0)))
+        }
+    }
+
+    pub fn idle_source() -> Option<Box<dyn IdleSource>> {
+        Some(Box::new(CoreGraphicsIdleSource))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod platform {
+    use super::IdleSource;
+
+    pub fn idle_source() -> Option<Box<dyn IdleSource>> {
+        None
+    }
+}
@@ -1,6 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use presence_bridge_core::{PlaybackState, SourceApp, Track};
+use presence_bridge_core::{
+    clamp_position, AppConfig, PlaybackState, ProviderSelection, SourceApp, Track,
+};
+use std::time::{Duration, Instant};
+use tracing::debug;
 
 #[derive(Debug, Clone)]
 pub struct ProviderSnapshot {
@@ -8,7 +12,10 @@ pub struct ProviderSnapshot {
     pub state: PlaybackState,
     pub track: Option<Track>,
     pub raw_state: Option<String>,
-    pub last_error: Option<String>,
+    pub last_error: Option<ProviderError>,
+    /// What the provider behind this snapshot reports reliably; filled in by
+    /// [`ProviderChain`], conservative elsewhere.
+    pub capabilities: ProviderCapabilities,
 }
 
 impl ProviderSnapshot {
@@ -19,43 +26,106 @@ impl ProviderSnapshot {
             track: None,
             raw_state: Some("stopped".to_string()),
             last_error: None,
+            capabilities: ProviderCapabilities::default(),
         }
     }
 
-    pub fn with_error(provider_name: &'static str, err: impl ToString) -> Self {
+    /// True when no real provider produced this snapshot (none configured or available).
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.provider_name, "none" | "null")
+    }
+
+    pub fn with_error(provider_name: &'static str, err: ProviderError) -> Self {
         Self {
             provider_name,
             state: PlaybackState::Stopped,
             track: None,
             raw_state: Some("error".to_string()),
-            last_error: Some(err.to_string()),
+            last_error: Some(err),
+            capabilities: ProviderCapabilities::default(),
         }
     }
 }
 
+/// What a provider reports reliably, so callers know which signals to trust.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    /// Reports seeks as they happen, so a position jump is deliberate.
+    pub seek_signals: bool,
+    /// Reports an artwork URL for the track.
+    pub art_url: bool,
+    /// The position is current at every poll rather than a periodically refreshed value.
+    pub accurate_position: bool,
+}
+
 #[async_trait]
 pub trait NowPlayingProvider: Send {
     fn name(&self) -> &'static str;
     fn source(&self) -> SourceApp;
-    async fn poll(&mut self) -> Result<ProviderSnapshot>;
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError>;
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::default()
+    }
 }
 
 pub struct ProviderChain {
     providers: Vec<Box<dyn NowPlayingProvider>>,
+    selection: ProviderSelection,
+    stop_hysteresis: Duration,
+    /// Each provider's last active snapshot and when it was read.
+    last_active: Vec<Option<(ProviderSnapshot, Instant)>>,
 }
 
 impl ProviderChain {
     pub fn new(providers: Vec<Box<dyn NowPlayingProvider>>) -> Self {
-        Self { providers }
+        let last_active = providers.iter().map(|_| None).collect();
+        Self {
+            providers,
+            selection: ProviderSelection::default(),
+            stop_hysteresis: Duration::ZERO,
+            last_active,
+        }
+    }
+
+    pub fn with_selection(mut self, selection: ProviderSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_stop_hysteresis(mut self, hysteresis: Duration) -> Self {
+        self.stop_hysteresis = hysteresis;
+        self
     }
 
     pub async fn poll_best(&mut self) -> ProviderSnapshot {
+        self.poll_best_at(Instant::now()).await
+    }
+
+    async fn poll_best_at(&mut self, now: Instant) -> ProviderSnapshot {
         let mut fallback: Option<ProviderSnapshot> = None;
-        for provider in self.providers.iter_mut() {
+        let mut paused: Option<ProviderSnapshot> = None;
+        for (provider, last_active) in self.providers.iter_mut().zip(&mut self.last_active) {
             match provider.poll().await {
-                Ok(snapshot) => {
-                    if snapshot.state != PlaybackState::Stopped || snapshot.track.is_some() {
-                        return snapshot;
+                Ok(mut snapshot) => {
+                    snapshot.capabilities = provider.capabilities();
+                    let snapshot = smooth_stop(snapshot, last_active, self.stop_hysteresis, now);
+                    let active =
+                        snapshot.state != PlaybackState::Stopped || snapshot.track.is_some();
+                    match self.selection {
+                        ProviderSelection::FirstNonStopped if active => return snapshot,
+                        ProviderSelection::PreferPlaying
+                            if snapshot.state == PlaybackState::Playing =>
+                        {
+                            return snapshot
+                        }
+                        ProviderSelection::PreferPlaying if active => {
+                            if paused.is_none() {
+                                paused = Some(snapshot);
+                            }
+                            continue;
+                        }
+                        _ => {}
                     }
                     if fallback.is_none() {
                         fallback = Some(snapshot);
@@ -69,7 +139,9 @@ impl ProviderChain {
             }
         }
 
-        fallback.unwrap_or_else(|| ProviderSnapshot::stopped("none"))
+        paused
+            .or(fallback)
+            .unwrap_or_else(|| ProviderSnapshot::stopped("none"))
     }
 
     pub fn provider_names(&self) -> Vec<&'static str> {
@@ -77,35 +149,141 @@ impl ProviderChain {
     }
 }
 
-pub fn build_provider_chain(priority: &[String]) -> ProviderChain {
-    let mut providers: Vec<Box<dyn NowPlayingProvider>> = Vec::new();
-
-    for item in priority {
-        match item.as_str() {
-            "apple_music" => {
-                if let Some(p) = platform::apple_music_provider() {
-                    providers.push(p);
-                }
-            }
-            "windows" => {
-                if let Some(p) = platform::windows_provider() {
-                    providers.push(p);
-                }
-            }
-            "mpris" => {
-                if let Some(p) = platform::mpris_provider() {
-                    providers.push(p);
-                }
+/// Remembers `snapshot` when it is active; a clean stopped read within `hysteresis` of the
+/// last active one is taken for a glitch and answered with that snapshot instead, its
+/// position advanced if it was playing. A stop that outlasts the window passes through.
+fn smooth_stop(
+    snapshot: ProviderSnapshot,
+    last_active: &mut Option<(ProviderSnapshot, Instant)>,
+    hysteresis: Duration,
+    now: Instant,
+) -> ProviderSnapshot {
+    if snapshot.state != PlaybackState::Stopped || snapshot.track.is_some() {
+        *last_active = Some((snapshot.clone(), now));
+        return snapshot;
+    }
+    if snapshot.last_error.is_some() {
+        return snapshot;
+    }
+    match last_active.take() {
+        Some((cached, at)) if now.saturating_duration_since(at) < hysteresis => {
+            let mut masked = cached.clone();
+            if let Some(track) = masked.track.as_mut().filter(|t| t.is_playing) {
+                let elapsed = now.saturating_duration_since(at).as_millis() as u64;
+                track.position_ms =
+                    clamp_position(track.position_ms.map(|p| p + elapsed), track.duration_ms);
             }
-            _ => {}
+            debug!(
+                provider = snapshot.provider_name,
+                "ignoring a brief stopped read"
+            );
+            *last_active = Some((cached, at));
+            masked
         }
+        _ => snapshot,
+    }
+}
+
+/// Provider names accepted in `provider_priority` and by `--provider`.
+#[cfg(not(feature = "mock"))]
+pub const PROVIDER_NAMES: &[&str] = &["apple_music", "windows", "mpris", "mpd", "file"];
+#[cfg(feature = "mock")]
+pub const PROVIDER_NAMES: &[&str] = &["apple_music", "windows", "mpris", "mpd", "file", "mock"];
+
+/// `provider_priority` value that selects [`platform_provider_priority`].
+pub const AUTO_PROVIDER: &str = "auto";
+
+/// The desktop media integration compiled for this OS.
+pub fn platform_provider_priority() -> Vec<&'static str> {
+    if cfg!(target_os = "macos") {
+        vec!["apple_music"]
+    } else if cfg!(windows) {
+        vec!["windows"]
+    } else if cfg!(target_os = "linux") {
+        vec!["mpris"]
+    } else {
+        Vec::new()
     }
+}
+
+/// `provider_priority` with an empty list or `["auto"]` resolved for the current OS.
+pub fn effective_provider_priority(cfg: &AppConfig) -> Vec<String> {
+    match cfg.provider_priority.as_slice() {
+        [] => platform_provider_priority(),
+        [only] if only == AUTO_PROVIDER => platform_provider_priority(),
+        explicit => return explicit.to_vec(),
+    }
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+pub fn build_provider_chain(cfg: &AppConfig) -> ProviderChain {
+    let mut providers: Vec<Box<dyn NowPlayingProvider>> = effective_provider_priority(cfg)
+        .iter()
+        .filter_map(|item| provider_by_name(cfg, item))
+        .collect();
 
     if providers.is_empty() {
         providers.push(Box::new(NullProvider));
     }
 
     ProviderChain::new(providers)
+        .with_selection(cfg.provider_selection)
+        .with_stop_hysteresis(Duration::from_millis(
+            cfg.intervals.provider_stop_hysteresis_ms,
+        ))
+}
+
+/// True when `new` builds a different chain than `old`, so a reload has to rebuild it.
+pub fn provider_config_changed(old: &AppConfig, new: &AppConfig) -> bool {
+    old.provider_priority != new.provider_priority
+        || old.provider_selection != new.provider_selection
+        || old.apple_music != new.apple_music
+        || old.mpd != new.mpd
+        || old.file != new.file
+        || old.mpris_allow != new.mpris_allow
+        || old.mpris_deny != new.mpris_deny
+        || old.mpris_title_separator != new.mpris_title_separator
+        || old.intervals.provider_stop_hysteresis_ms != new.intervals.provider_stop_hysteresis_ms
+}
+
+/// Builds a chain holding only `name`, ignoring `provider_priority`.
+pub fn build_single_provider_chain(cfg: &AppConfig, name: &str) -> Result<ProviderChain> {
+    if !PROVIDER_NAMES.contains(&name) {
+        bail!(
+            "unknown provider '{name}'; valid options: {}",
+            PROVIDER_NAMES.join(", ")
+        );
+    }
+    let provider = provider_by_name(cfg, name).ok_or_else(|| {
+        anyhow!("provider '{name}' is not available on this platform or is not configured")
+    })?;
+    Ok(ProviderChain::new(vec![provider])
+        .with_selection(cfg.provider_selection)
+        .with_stop_hysteresis(Duration::from_millis(
+            cfg.intervals.provider_stop_hysteresis_ms,
+        )))
+}
+
+fn provider_by_name(cfg: &AppConfig, name: &str) -> Option<Box<dyn NowPlayingProvider>> {
+    match name {
+        "apple_music" => platform::apple_music_provider(cfg),
+        "windows" => platform::windows_provider(),
+        "mpris" => platform::mpris_provider(cfg),
+        "mpd" => Some(Box::new(mpd::MpdProvider::new(
+            cfg.mpd.host.clone(),
+            cfg.mpd.port,
+        ))),
+        "file" => cfg
+            .file
+            .path
+            .clone()
+            .map(|path| Box::new(file::FileProvider::new(path)) as Box<dyn NowPlayingProvider>),
+        #[cfg(feature = "mock")]
+        "mock" => Some(Box::new(mock::MockProvider::demo())),
+        _ => None,
+    }
 }
 
 struct NullProvider;
@@ -120,31 +298,39 @@ impl NowPlayingProvider for NullProvider {
         SourceApp::Unknown
     }
 
-    async fn poll(&mut self) -> Result<ProviderSnapshot> {
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
         Ok(ProviderSnapshot::stopped(self.name()))
     }
 }
 
 mod platform {
     use super::NowPlayingProvider;
+    use presence_bridge_core::AppConfig;
 
     #[cfg(target_os = "linux")]
-    pub fn mpris_provider() -> Option<Box<dyn NowPlayingProvider>> {
-        Some(Box::new(crate::mpris::MprisProvider::new()))
+    pub fn mpris_provider(cfg: &AppConfig) -> Option<Box<dyn NowPlayingProvider>> {
+        Some(Box::new(
+            crate::mpris::MprisProvider::new()
+                .with_player_filter(cfg.mpris_allow.clone(), cfg.mpris_deny.clone())
+                .with_title_separator(cfg.mpris_title_separator.clone()),
+        ))
     }
 
     #[cfg(not(target_os = "linux"))]
-    pub fn mpris_provider() -> Option<Box<dyn NowPlayingProvider>> {
+    pub fn mpris_provider(_cfg: &AppConfig) -> Option<Box<dyn NowPlayingProvider>> {
         None
     }
 
     #[cfg(target_os = "macos")]
-    pub fn apple_music_provider() -> Option<Box<dyn NowPlayingProvider>> {
-        Some(Box::new(crate::macos::AppleMusicProvider::new()))
+    pub fn apple_music_provider(cfg: &AppConfig) -> Option<Box<dyn NowPlayingProvider>> {
+        Some(Box::new(
+            crate::macos::AppleMusicProvider::new(cfg.apple_music.osascript_attempts)
+                .with_script_path(cfg.apple_music.jxa_script_path.clone()),
+        ))
     }
 
     #[cfg(not(target_os = "macos"))]
-    pub fn apple_music_provider() -> Option<Box<dyn NowPlayingProvider>> {
+    pub fn apple_music_provider(_cfg: &AppConfig) -> Option<Box<dyn NowPlayingProvider>> {
         None
     }
 
@@ -159,9 +345,215 @@ mod platform {
     }
 }
 
+mod error;
+mod file;
+pub mod focus;
+pub mod idle;
 #[cfg(target_os = "macos")]
 mod macos;
+pub use error::ProviderError;
+#[cfg(target_os = "macos")]
+pub use macos::{AppleMusicProvider, CommandRunner};
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mock")]
+pub use mock::{MockProvider, MockStep};
+mod mpd;
 #[cfg(target_os = "linux")]
 mod mpris;
 #[cfg(target_os = "windows")]
 mod windows;
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_single_provider_chain, effective_provider_priority, NowPlayingProvider,
+        ProviderCapabilities, ProviderChain, ProviderError, ProviderSnapshot,
+    };
+    use async_trait::async_trait;
+    use presence_bridge_core::{AppConfig, PlaybackState, ProviderSelection, SourceApp, Track};
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant, SystemTime};
+
+    struct FixedProvider(&'static str, PlaybackState);
+
+    #[async_trait]
+    impl NowPlayingProvider for FixedProvider {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn source(&self) -> SourceApp {
+            SourceApp::Unknown
+        }
+
+        async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+            let mut snapshot = ProviderSnapshot::stopped(self.0);
+            snapshot.state = self.1;
+            Ok(snapshot)
+        }
+    }
+
+    /// Plays one track, reading stopped whenever its script says so.
+    struct FlakyProvider(VecDeque<PlaybackState>);
+
+    #[async_trait]
+    impl NowPlayingProvider for FlakyProvider {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn source(&self) -> SourceApp {
+            SourceApp::Unknown
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities {
+                seek_signals: true,
+                ..ProviderCapabilities::default()
+            }
+        }
+
+        async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+            let mut snapshot = ProviderSnapshot::stopped("flaky");
+            if self.0.pop_front() == Some(PlaybackState::Playing) {
+                snapshot.state = PlaybackState::Playing;
+                snapshot.track = Some(Track {
+                    id: "a:t".to_string(),
+                    title: "T".to_string(),
+                    artist: "A".to_string(),
+                    album: None,
+                    track_number: None,
+                    year: None,
+                    duration_ms: Some(200_000),
+                    position_ms: Some(10_000),
+                    is_playing: true,
+                    source: SourceApp::Unknown,
+                    player: None,
+                    is_podcast: false,
+                    links: Default::default(),
+                    updated_at: SystemTime::now(),
+                });
+            }
+            Ok(snapshot)
+        }
+    }
+
+    fn chain(
+        states: &[(&'static str, PlaybackState)],
+        selection: ProviderSelection,
+    ) -> ProviderChain {
+        let providers: Vec<Box<dyn NowPlayingProvider>> = states
+            .iter()
+            .map(|(name, state)| {
+                Box::new(FixedProvider(name, *state)) as Box<dyn NowPlayingProvider>
+            })
+            .collect();
+        ProviderChain::new(providers).with_selection(selection)
+    }
+
+    #[tokio::test]
+    async fn selection_policies_pick_expected_provider() {
+        use PlaybackState::{Paused, Playing, Stopped};
+        let mixed = [("a", Stopped), ("b", Paused), ("c", Playing)];
+
+        let first = chain(&mixed, ProviderSelection::FirstNonStopped)
+            .poll_best()
+            .await;
+        assert_eq!(first.provider_name, "b");
+        let playing = chain(&mixed, ProviderSelection::PreferPlaying)
+            .poll_best()
+            .await;
+        assert_eq!(playing.provider_name, "c");
+
+        let paused_only = [("a", Stopped), ("b", Paused), ("c", Paused)];
+        let snap = chain(&paused_only, ProviderSelection::PreferPlaying)
+            .poll_best()
+            .await;
+        assert_eq!(snap.provider_name, "b");
+
+        let stopped_only = [("a", Stopped), ("b", Stopped)];
+        let snap = chain(&stopped_only, ProviderSelection::PreferPlaying)
+            .poll_best()
+            .await;
+        assert_eq!(snap.provider_name, "a");
+        assert_eq!(snap.state, Stopped);
+    }
+
+    #[tokio::test]
+    async fn brief_stop_is_masked_but_a_sustained_one_passes() {
+        use PlaybackState::{Playing, Stopped};
+        let provider = FlakyProvider(VecDeque::from([Playing, Stopped, Stopped, Stopped]));
+        let providers: Vec<Box<dyn NowPlayingProvider>> =
+            vec![Box::new(provider), Box::new(FixedProvider("idle", Stopped))];
+        let mut chain = ProviderChain::new(providers).with_stop_hysteresis(Duration::from_secs(2));
+        let start = Instant::now();
+
+        assert_eq!(chain.poll_best_at(start).await.state, Playing);
+        let masked = chain.poll_best_at(start + Duration::from_secs(1)).await;
+        assert_eq!(masked.provider_name, "flaky");
+        assert_eq!(masked.state, Playing);
+        assert_eq!(masked.track.expect("track").position_ms, Some(11_000));
+
+        let stopped = chain.poll_best_at(start + Duration::from_secs(3)).await;
+        assert_eq!(stopped.state, Stopped);
+        assert!(stopped.track.is_none());
+        let still = chain.poll_best_at(start + Duration::from_secs(4)).await;
+        assert_eq!(still.state, Stopped);
+    }
+
+    #[tokio::test]
+    async fn stops_pass_through_without_hysteresis() {
+        use PlaybackState::{Playing, Stopped};
+        let provider = FlakyProvider(VecDeque::from([Playing, Stopped]));
+        let mut chain = ProviderChain::new(vec![Box::new(provider)]);
+        let start = Instant::now();
+        let playing = chain.poll_best_at(start).await;
+        assert_eq!(playing.state, Playing);
+        assert!(playing.capabilities.seek_signals);
+        assert_eq!(chain.poll_best_at(start).await.state, Stopped);
+    }
+
+    #[test]
+    fn auto_priority_matches_compiled_target() {
+        let expected: &[&str] = if cfg!(target_os = "macos") {
+            &["apple_music"]
+        } else if cfg!(windows) {
+            &["windows"]
+        } else if cfg!(target_os = "linux") {
+            &["mpris"]
+        } else {
+            &[]
+        };
+        for priority in [vec![], vec!["auto".to_string()]] {
+            let cfg = AppConfig {
+                provider_priority: priority,
+                ..AppConfig::default()
+            };
+            assert_eq!(effective_provider_priority(&cfg), expected);
+        }
+
+        let explicit = AppConfig {
+            provider_priority: vec!["mpd".into(), "file".into()],
+            ..AppConfig::default()
+        };
+        assert_eq!(effective_provider_priority(&explicit), ["mpd", "file"]);
+    }
+
+    #[test]
+    fn single_provider_chain_ignores_priority() {
+        let cfg = AppConfig {
+            provider_priority: vec!["apple_music".into(), "windows".into()],
+            ..AppConfig::default()
+        };
+        let chain = build_single_provider_chain(&cfg, "mpd").expect("mpd chain");
+        assert_eq!(chain.provider_names(), vec!["mpd"]);
+
+        let err = build_single_provider_chain(&cfg, "winamp")
+            .err()
+            .expect("unknown provider");
+        assert!(err
+            .to_string()
+            .contains("valid options: apple_music, windows, mpris, mpd, file"));
+    }
+}
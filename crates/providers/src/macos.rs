@@ -1,14 +1,57 @@
-use crate::{NowPlayingProvider, ProviderSnapshot};
-use anyhow::{anyhow, Context, Result};
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use presence_bridge_core::{urls, PlaybackState, SourceApp, Track, TrackLinks};
+use presence_bridge_core::text::clean_metadata;
+use presence_bridge_core::{
+    clamp_position, is_podcast_genre, urls, PlaybackState, SearchQueryMode, SourceApp, Track,
+};
 use serde::Deserialize;
+use std::ffi::OsStr;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::process::Output;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
 use tokio::process::Command;
+use tracing::debug;
 
-#[derive(Default)]
-pub struct AppleMusicProvider;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Raw output kept in `last_error` when the script's JSON can't be parsed.
+const RAW_OUTPUT_LIMIT: usize = 200;
+
+const SCRIPT_NAME: &str = "jxa_now_playing.js";
+
+/// Built into the binary so installed copies don't depend on the source tree.
+const EMBEDDED_SCRIPT: &str = include_str!("../macos/jxa_now_playing.js");
+
+const AUTOMATION_DENIED: &str = "macOS Automation permission to control Music was denied; re-grant it in System Settings > Privacy & Security > Automation";
+
+/// Runs `osascript` with the given arguments. The default spawns the real binary; tests
+/// and embedders can inject canned output instead.
+#[async_trait]
+pub trait CommandRunner: Send + Sync {
+    async fn run(&self, args: &[&OsStr]) -> Result<Output>;
+}
+
+struct OsascriptRunner;
+
+#[async_trait]
+impl CommandRunner for OsascriptRunner {
+    async fn run(&self, args: &[&OsStr]) -> Result<Output> {
+        Command::new("osascript")
+            .args(args)
+            .output()
+            .await
+            .context("failed to run osascript for Apple Music")
+    }
+}
+
+pub struct AppleMusicProvider {
+    runner: Box<dyn CommandRunner>,
+    attempts: u32,
+    script_override: Option<PathBuf>,
+    embedded: Option<EmbeddedScript>,
+}
 
 #[derive(Debug, Deserialize)]
 struct JxaResult {
@@ -16,22 +59,99 @@ struct JxaResult {
     title: Option<String>,
     artist: Option<String>,
     album: Option<String>,
+    #[serde(rename = "trackNumber")]
+    track_number: Option<u32>,
+    year: Option<u32>,
     duration: Option<u64>,
     position: Option<u64>,
     #[serde(rename = "persistentId")]
     persistent_id: Option<String>,
+    #[serde(rename = "mediaKind")]
+    media_kind: Option<String>,
+    genre: Option<String>,
     error: Option<String>,
 }
 
 impl AppleMusicProvider {
-    pub fn new() -> Self {
-        Self
+    pub fn new(attempts: u32) -> Self {
+        Self::with_runner(Box::new(OsascriptRunner), attempts)
+    }
+
+    pub fn with_runner(runner: Box<dyn CommandRunner>, attempts: u32) -> Self {
+        Self {
+            runner,
+            attempts: attempts.max(1),
+            script_override: None,
+            embedded: None,
+        }
+    }
+
+    /// Runs this JXA script instead of the embedded one.
+    pub fn with_script_path(mut self, path: Option<PathBuf>) -> Self {
+        self.script_override = path;
+        self
+    }
+
+    /// Runs the script, retrying transient failures but not a denied Automation permission.
+    async fn run_script(&mut self) -> Result<Output> {
+        let script = self.script_path()?;
+        let args = [
+            OsStr::new("-l"),
+            OsStr::new("JavaScript"),
+            script.as_os_str(),
+        ];
+        let mut attempt = 1;
+        loop {
+            let result = self.runner.run(&args).await;
+            let retryable = match &result {
+                Ok(output) if output.status.success() => return result,
+                Ok(output) => !is_automation_denied(&String::from_utf8_lossy(&output.stderr)),
+                Err(_) => true,
+            };
+            if !retryable || attempt >= self.attempts {
+                return result;
+            }
+            debug!(attempt, "osascript failed; retrying");
+            tokio::time::sleep(RETRY_DELAY).await;
+            attempt += 1;
+        }
+    }
+
+    /// The override when configured, otherwise the embedded script, written out once.
+    fn script_path(&mut self) -> Result<PathBuf> {
+        if let Some(path) = &self.script_override {
+            return Ok(path.clone());
+        }
+        if let Some(embedded) = &self.embedded {
+            return Ok(embedded.path.clone());
+        }
+        let embedded = self.embedded.insert(EmbeddedScript::write()?);
+        Ok(embedded.path.clone())
     }
+}
+
+/// The embedded script written to a temp file for `osascript`; removed on drop.
+struct EmbeddedScript {
+    path: PathBuf,
+}
+
+impl EmbeddedScript {
+    fn write() -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "presence-bridge-{}-{}-{SCRIPT_NAME}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, EMBEDDED_SCRIPT)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
 
-    fn script_path() -> PathBuf {
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("macos")
-            .join("jxa_now_playing.js")
+impl Drop for EmbeddedScript {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
     }
 }
 
@@ -45,40 +165,66 @@ impl NowPlayingProvider for AppleMusicProvider {
         SourceApp::AppleMusicMac
     }
 
-    async fn poll(&mut self) -> Result<ProviderSnapshot> {
-        let output = Command::new("osascript")
-            .arg("-l")
-            .arg("JavaScript")
-            .arg(Self::script_path())
-            .output()
-            .await
-            .context("failed to run osascript for Apple Music")?;
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            accurate_position: true,
+            ..ProviderCapabilities::default()
+        }
+    }
+
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+        let output = self.run_script().await?;
 
         if !output.status.success() {
-            return Err(anyhow!(
-                "osascript failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if is_automation_denied(&stderr) {
+                return Ok(ProviderSnapshot::with_error(
+                    self.name(),
+                    ProviderError::PermissionDenied(AUTOMATION_DENIED.to_string()),
+                ));
+            }
+            return Err(osascript_error(&stderr));
         }
 
         let stdout = String::from_utf8(output.stdout).context("invalid UTF-8 from osascript")?;
-        let parsed: JxaResult =
-            serde_json::from_str(stdout.trim()).context("invalid JSON from jxa script")?;
+        let Some(parsed) = last_json_object(&stdout) else {
+            return Ok(ProviderSnapshot::with_error(
+                self.name(),
+                ProviderError::Parse(format!(
+                    "invalid JSON from jxa script: {:?}",
+                    truncate_raw(stdout.trim())
+                )),
+            ));
+        };
 
         if let Some(err) = parsed.error {
-            return Ok(ProviderSnapshot::with_error(self.name(), err));
+            if is_automation_denied(&err) {
+                return Ok(ProviderSnapshot::with_error(
+                    self.name(),
+                    ProviderError::PermissionDenied(AUTOMATION_DENIED.to_string()),
+                ));
+            }
+            return Ok(ProviderSnapshot::with_error(
+                self.name(),
+                ProviderError::Other(err),
+            ));
         }
 
         match parsed.state.as_str() {
             "playing" | "paused" => {
-                let title = parsed.title.unwrap_or_else(|| "Unknown Title".to_string());
+                let title = parsed
+                    .title
+                    .map(|t| clean_metadata(&t))
+                    .filter(|t| !t.is_empty())
+                    .unwrap_or_else(|| "Unknown Title".to_string());
                 let artist = parsed
                     .artist
+                    .map(|a| clean_metadata(&a))
+                    .filter(|a| !a.is_empty())
                     .unwrap_or_else(|| "Unknown Artist".to_string());
-                let links = TrackLinks {
-                    apple_music: Some(urls::apple_music_search_url(&artist, &title)),
-                    spotify_search: Some(urls::spotify_search_url(&artist, &title)),
-                };
+                let is_podcast = parsed.media_kind.as_deref() == Some("podcast")
+                    || parsed.genre.as_deref().is_some_and(is_podcast_genre);
+                let links = urls::track_links(&artist, &title, SearchQueryMode::Raw);
 
                 let track = Track {
                     id: parsed
@@ -87,11 +233,18 @@ impl NowPlayingProvider for AppleMusicProvider {
                         .unwrap_or_else(|| format!("{}:{}", artist, title)),
                     title,
                     artist,
-                    album: parsed.album,
+                    album: parsed
+                        .album
+                        .map(|a| clean_metadata(&a))
+                        .filter(|a| !a.is_empty()),
+                    track_number: parsed.track_number.filter(|n| *n > 0),
+                    year: parsed.year.filter(|y| *y > 0),
                     duration_ms: parsed.duration,
-                    position_ms: parsed.position,
+                    position_ms: clamp_position(parsed.position, parsed.duration),
                     is_playing: parsed.state == "playing",
                     source: SourceApp::AppleMusicMac,
+                    player: None,
+                    is_podcast,
                     links,
                     updated_at: SystemTime::now(),
                 };
@@ -106,9 +259,322 @@ impl NowPlayingProvider for AppleMusicProvider {
                     track: Some(track),
                     raw_state: Some(parsed.state),
                     last_error: None,
+                    capabilities: ProviderCapabilities::default(),
                 })
             }
             _ => Ok(ProviderSnapshot::stopped(self.name())),
         }
     }
 }
+
+/// Finds the last well-formed result object, skipping stray log lines or banners around it.
+fn last_json_object(stdout: &str) -> Option<JxaResult> {
+    let mut found = None;
+    let mut rest = stdout;
+    while let Some(start) = rest.find('{') {
+        let mut values =
+            serde_json::Deserializer::from_str(&rest[start..]).into_iter::<JxaResult>();
+        match values.next() {
+            Some(Ok(parsed)) => {
+                found = Some(parsed);
+                rest = &rest[start + values.byte_offset()..];
+            }
+            _ => rest = &rest[start + 1..],
+        }
+    }
+    found
+}
+
+fn truncate_raw(raw: &str) -> &str {
+    match raw.char_indices().nth(RAW_OUTPUT_LIMIT) {
+        Some((end, _)) => &raw[..end],
+        None => raw,
+    }
+}
+
+/// Classifies a failed run by the AppleScript error number in stderr.
+fn osascript_error(stderr: &str) -> ProviderError {
+    let message = format!("osascript failed: {}", stderr.trim());
+    if stderr.contains("(-600)") {
+        ProviderError::NotRunning(message)
+    } else if stderr.contains("(-1712)") {
+        ProviderError::Timeout(message)
+    } else {
+        ProviderError::Other(message)
+    }
+}
+
+/// Matches errAEEventNotPermitted (-1743), raised when Automation access is revoked.
+fn is_automation_denied(message: &str) -> bool {
+    message.contains("-1743") || message.contains("Not authorized to send Apple events")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_automation_denied, last_json_object, AppleMusicProvider, CommandRunner, EMBEDDED_SCRIPT,
+    };
+    use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use presence_bridge_core::PlaybackState;
+    use std::collections::VecDeque;
+    use std::ffi::OsStr;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::PathBuf;
+    use std::process::{ExitStatus, Output};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Replays canned `osascript` results and counts calls.
+    #[derive(Clone, Default)]
+    struct ScriptedRunner {
+        outputs: Arc<Mutex<VecDeque<Output>>>,
+        calls: Arc<AtomicUsize>,
+        scripts: Arc<Mutex<Vec<PathBuf>>>,
+    }
+
+    impl ScriptedRunner {
+        fn new(outputs: Vec<(i32, &str, &str)>) -> Self {
+            let outputs = outputs
+                .into_iter()
+                .map(|(code, stdout, stderr)| Output {
+                    status: ExitStatus::from_raw(code << 8),
+                    stdout: stdout.as_bytes().to_vec(),
+                    stderr: stderr.as_bytes().to_vec(),
+                })
+                .collect();
+            Self {
+                outputs: Arc::new(Mutex::new(outputs)),
+                calls: Arc::default(),
+                scripts: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CommandRunner for ScriptedRunner {
+        async fn run(&self, args: &[&OsStr]) -> Result<Output> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.scripts
+                .lock()
+                .unwrap()
+                .extend(args.last().map(PathBuf::from));
+            Ok(self
+                .outputs
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("unexpected osascript call"))
+        }
+    }
+
+    const PLAYING: &str = r#"{"state":"playing","title":"Get Lucky","artist":"Daft Punk"}"#;
+
+    async fn poll_fixture(stdout: &str) -> super::ProviderSnapshot {
+        let runner = ScriptedRunner::new(vec![(0, stdout, "")]);
+        AppleMusicProvider::with_runner(Box::new(runner), 1)
+            .poll()
+            .await
+            .expect("snapshot")
+    }
+
+    #[tokio::test]
+    async fn maps_playing_fixture() {
+        let snapshot = poll_fixture(
+            r#"{"state":"playing","title":"Get Lucky","artist":"Daft Punk","album":"Random Access Memories","trackNumber":8,"year":2013,"duration":248000,"position":250000,"persistentId":"ABC123"}"#,
+        )
+        .await;
+        assert_eq!(snapshot.state, PlaybackState::Playing);
+        assert_eq!(snapshot.raw_state.as_deref(), Some("playing"));
+        let track = snapshot.track.expect("track");
+        assert_eq!(track.id, "ABC123");
+        assert_eq!(track.album.as_deref(), Some("Random Access Memories"));
+        assert_eq!((track.track_number, track.year), (Some(8), Some(2013)));
+        assert_eq!(track.position_ms, Some(248_000));
+        assert!(track.is_playing);
+    }
+
+    #[tokio::test]
+    async fn podcast_media_kind_or_genre_marks_an_episode() {
+        let by_kind = poll_fixture(
+            r#"{"state":"playing","title":"Episode 12","artist":"The Show","mediaKind":"podcast"}"#,
+        )
+        .await;
+        assert!(by_kind.track.expect("track").is_podcast);
+
+        let by_genre = poll_fixture(
+            r#"{"state":"playing","title":"Episode 12","artist":"The Show","mediaKind":"song","genre":"Podcast"}"#,
+        )
+        .await;
+        assert!(by_genre.track.expect("track").is_podcast);
+
+        let song = poll_fixture(
+            r#"{"state":"playing","title":"Get Lucky","artist":"Daft Punk","mediaKind":"song","genre":"Electronic"}"#,
+        )
+        .await;
+        assert!(!song.track.expect("track").is_podcast);
+    }
+
+    #[tokio::test]
+    async fn maps_paused_stopped_and_error_fixtures() {
+        let paused =
+            poll_fixture(r#"{"state":"paused","title":"Get Lucky","trackNumber":0}"#).await;
+        assert_eq!(paused.state, PlaybackState::Paused);
+        let track = paused.track.expect("track");
+        assert!(!track.is_playing);
+        assert_eq!(track.artist, "Unknown Artist");
+        assert_eq!(track.track_number, None);
+
+        let stopped = poll_fixture(r#"{"state":"stopped"}"#).await;
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        assert!(stopped.track.is_none());
+
+        let error = poll_fixture(r#"{"state":"error","error":"Error: Music is busy"}"#).await;
+        assert!(error.track.is_none());
+        assert_eq!(
+            error.last_error,
+            Some(ProviderError::Other("Error: Music is busy".to_string()))
+        );
+
+        let garbage = poll_fixture("not json at all").await;
+        assert!(matches!(
+            garbage.last_error,
+            Some(ProviderError::Parse(raw)) if raw.contains("not json at all")
+        ));
+    }
+
+    #[tokio::test]
+    async fn failing_osascript_is_an_error_after_last_attempt() {
+        let runner = ScriptedRunner::new(vec![(1, "", "execution error (-1712)")]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner), 1);
+        assert!(matches!(
+            provider.poll().await,
+            Err(ProviderError::Timeout(_))
+        ));
+
+        let runner = ScriptedRunner::new(vec![(
+            1,
+            "",
+            "execution error: Music isn't running. (-600)",
+        )]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner), 1);
+        assert!(matches!(
+            provider.poll().await,
+            Err(ProviderError::NotRunning(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_osascript_failure() {
+        let runner = ScriptedRunner::new(vec![
+            (
+                1,
+                "",
+                "execution error: Music got an error: AppleEvent timed out. (-1712)",
+            ),
+            (0, PLAYING, ""),
+        ]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner.clone()), 3);
+
+        let snapshot = provider.poll().await.expect("snapshot");
+        assert_eq!(snapshot.state, PlaybackState::Playing);
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_denied_automation() {
+        let runner = ScriptedRunner::new(vec![(
+            1,
+            "",
+            "execution error: Not authorized to send Apple events to Music. (-1743)",
+        )]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner.clone()), 3);
+
+        let snapshot = provider.poll().await.expect("snapshot");
+        assert!(matches!(
+            snapshot.last_error,
+            Some(ProviderError::PermissionDenied(_))
+        ));
+        assert_eq!(runner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn detects_automation_permission_denied() {
+        let stderr = "jxa_now_playing.js: execution error: Error: Error: Not authorized to send Apple events to Music. (-1743)\n";
+        assert!(is_automation_denied(stderr));
+        assert!(is_automation_denied(
+            "Error: Not authorized to send Apple events to Music."
+        ));
+        assert!(!is_automation_denied(
+            "execution error: Error: Application can't be found. (-2700)"
+        ));
+    }
+
+    #[test]
+    fn extracts_result_from_noisy_output() {
+        let json = r#"{"state":"playing","title":"Get Lucky","artist":"Daft Punk"}"#;
+        for stdout in [
+            format!("{json}\n"),
+            format!("2024-05-01 osascript[123] warning: {{stale}}\n{json}\n"),
+            format!("{json}\nScript finished {{ok}}\n"),
+        ] {
+            let parsed = last_json_object(&stdout).expect("result object");
+            assert_eq!(parsed.state, "playing");
+            assert_eq!(parsed.title.as_deref(), Some("Get Lucky"));
+        }
+
+        let last = last_json_object(&format!(r#"{{"state":"paused"}}{json}"#)).expect("last");
+        assert_eq!(last.state, "playing");
+    }
+
+    #[test]
+    fn malformed_output_has_no_result() {
+        assert!(last_json_object("").is_none());
+        assert!(last_json_object(r#"{"state":"playing","title":"#).is_none());
+        assert!(last_json_object("execution error: {not json}").is_none());
+    }
+
+    #[test]
+    fn advertises_live_position_only() {
+        assert_eq!(
+            AppleMusicProvider::new(1).capabilities(),
+            ProviderCapabilities {
+                accurate_position: true,
+                ..ProviderCapabilities::default()
+            }
+        );
+    }
+
+    #[test]
+    fn embedded_script_is_not_empty() {
+        assert!(EMBEDDED_SCRIPT.contains("Application"));
+    }
+
+    #[tokio::test]
+    async fn runs_embedded_script_unless_overridden() {
+        let runner = ScriptedRunner::new(vec![(0, PLAYING, ""), (0, PLAYING, "")]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner.clone()), 1);
+        provider.poll().await.expect("snapshot");
+        provider.poll().await.expect("snapshot");
+
+        let scripts = runner.scripts.lock().unwrap().clone();
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[0], scripts[1], "temp file is reused");
+        assert_eq!(
+            std::fs::read_to_string(&scripts[0]).unwrap(),
+            EMBEDDED_SCRIPT
+        );
+        drop(provider);
+        assert!(!scripts[0].exists(), "temp file is removed on drop");
+
+        let runner = ScriptedRunner::new(vec![(0, PLAYING, "")]);
+        let mut provider = AppleMusicProvider::with_runner(Box::new(runner.clone()), 1)
+            .with_script_path(Some(PathBuf::from("/custom/now_playing.js")));
+        provider.poll().await.expect("snapshot");
+        assert_eq!(
+            runner.scripts.lock().unwrap().as_slice(),
+            [PathBuf::from("/custom/now_playing.js")]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
+use async_trait::async_trait;
+use presence_bridge_core::{urls, PlaybackState, SearchQueryMode, SourceApp, Track};
+use std::time::{Duration, Instant, SystemTime};
+
+const STEP: Duration = Duration::from_secs(15);
+
+/// One entry of the script: `track` is `(artist, title, album, duration_ms)`, and
+/// `position_ms` is where it starts, advancing through the step while playing.
+#[derive(Debug, Clone)]
+pub struct MockStep {
+    pub state: PlaybackState,
+    pub track: Option<(&'static str, &'static str, &'static str, u64)>,
+    pub position_ms: u64,
+}
+
+/// Cycles through a fixed script of tracks and states, one step every 15 seconds, so the
+/// whole pipeline can be exercised without a real player.
+pub struct MockProvider {
+    script: Vec<MockStep>,
+    step: Duration,
+    started: Instant,
+}
+
+impl MockProvider {
+    pub fn new(script: Vec<MockStep>, step: Duration) -> Self {
+        Self {
+            script,
+            step,
+            started: Instant::now(),
+        }
+    }
+
+    /// Plays a track, pauses it, plays another, then stops, and starts over.
+    pub fn demo() -> Self {
+        let get_lucky = ("Daft Punk", "Get Lucky", "Random Access Memories", 248_000);
+        Self::new(
+            vec![
+                MockStep {
+                    state: PlaybackState::Playing,
+                    track: Some(get_lucky),
+                    position_ms: 30_000,
+                },
+                MockStep {
+                    state: PlaybackState::Paused,
+                    track: Some(get_lucky),
+                    position_ms: 45_000,
+                },
+                MockStep {
+                    state: PlaybackState::Playing,
+                    track: Some(("Justice", "D.A.N.C.E.", "†", 242_000)),
+                    position_ms: 0,
+                },
+                MockStep {
+                    state: PlaybackState::Stopped,
+                    track: None,
+                    position_ms: 0,
+                },
+            ],
+            STEP,
+        )
+    }
+
+    fn snapshot_at(&self, elapsed: Duration) -> ProviderSnapshot {
+        let name = self.name();
+        if self.script.is_empty() || self.step.is_zero() {
+            return ProviderSnapshot::stopped(name);
+        }
+        let steps = elapsed.as_millis() / self.step.as_millis();
+        let step = &self.script[(steps % self.script.len() as u128) as usize];
+        let Some((artist, title, album, duration_ms)) = step.track else {
+            return ProviderSnapshot::stopped(name);
+        };
+        if step.state == PlaybackState::Stopped {
+            return ProviderSnapshot::stopped(name);
+        }
+
+        let is_playing = step.state == PlaybackState::Playing;
+        let into_step = (elapsed.as_millis() % self.step.as_millis()) as u64;
+        let position_ms = if is_playing {
+            step.position_ms + into_step
+        } else {
+            step.position_ms
+        };
+        let track = Track {
+            id: format!("mock:{artist}:{title}"),
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: Some(album.to_string()),
+            track_number: None,
+            year: None,
+            duration_ms: Some(duration_ms),
+            position_ms: Some(position_ms.min(duration_ms)),
+            is_playing,
+            source: SourceApp::Unknown,
+            player: Some("mock".to_string()),
+            is_podcast: false,
+            links: urls::track_links(artist, title, SearchQueryMode::Raw),
+            updated_at: SystemTime::now(),
+        };
+        ProviderSnapshot {
+            provider_name: name,
+            state: step.state,
+            track: Some(track),
+            raw_state: Some(format!("{:?}", step.state).to_lowercase()),
+            last_error: None,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl NowPlayingProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn source(&self) -> SourceApp {
+        SourceApp::Unknown
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            accurate_position: true,
+            ..ProviderCapabilities::default()
+        }
+    }
+
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+        Ok(self.snapshot_at(self.started.elapsed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockProvider;
+    use crate::{NowPlayingProvider, ProviderCapabilities};
+    use presence_bridge_core::PlaybackState;
+    use std::time::Duration;
+
+    #[test]
+    fn advances_through_the_script_and_wraps() {
+        let mock = MockProvider::demo();
+        let at = |secs| mock.snapshot_at(Duration::from_secs(secs));
+
+        let first = at(5);
+        assert_eq!(first.state, PlaybackState::Playing);
+        let track = first.track.expect("track");
+        assert_eq!(
+            (track.artist.as_str(), track.title.as_str()),
+            ("Daft Punk", "Get Lucky")
+        );
+        assert_eq!(track.position_ms, Some(35_000));
+
+        let paused = at(20);
+        assert_eq!(paused.state, PlaybackState::Paused);
+        let paused_track = paused.track.expect("track");
+        assert_eq!(paused_track.id, track.id);
+        assert_eq!(paused_track.position_ms, Some(45_000));
+        assert_eq!(at(29).track.expect("track").position_ms, Some(45_000));
+
+        assert_eq!(at(30).track.expect("track").title, "D.A.N.C.E.");
+        let stopped = at(45);
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        assert!(stopped.track.is_none());
+
+        assert_eq!(at(60).track.expect("track").id, track.id);
+    }
+
+    #[test]
+    fn advertises_live_position_only() {
+        assert_eq!(
+            MockProvider::demo().capabilities(),
+            ProviderCapabilities {
+                accurate_position: true,
+                ..ProviderCapabilities::default()
+            }
+        );
+    }
+}
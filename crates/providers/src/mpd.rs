@@ -0,0 +1,332 @@
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use presence_bridge_core::text::{clean_metadata, parse_year};
+use presence_bridge_core::{
+    clamp_position, is_podcast_genre, urls, PlaybackState, SearchQueryMode, SourceApp, Track,
+};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Bounds the whole exchange after connecting, greeting included, so a server that
+/// accepts and then goes quiet can't stall the poll.
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct MpdProvider {
+    host: String,
+    port: u16,
+    connect_timeout: Duration,
+    io_timeout: Duration,
+}
+
+impl MpdProvider {
+    pub fn new(host: String, port: u16) -> Self {
+        Self {
+            host,
+            port,
+            connect_timeout: CONNECT_TIMEOUT,
+            io_timeout: IO_TIMEOUT,
+        }
+    }
+
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    async fn connect(&self) -> Result<TcpStream> {
+        let addr = self.addr();
+        tokio::time::timeout(self.connect_timeout, TcpStream::connect(&addr))
+            .await
+            .with_context(|| format!("timed out connecting to mpd at {addr}"))?
+            .with_context(|| format!("failed to connect to mpd at {addr}"))
+    }
+
+    async fn query(&self, stream: TcpStream) -> Result<(String, String)> {
+        tokio::time::timeout(self.io_timeout, exchange(BufReader::new(stream)))
+            .await
+            .with_context(|| format!("mpd at {} stopped responding", self.addr()))?
+    }
+}
+
+/// Reads the greeting, then asks for `status` and `currentsong`.
+async fn exchange(mut stream: BufReader<TcpStream>) -> Result<(String, String)> {
+    let mut greeting = String::new();
+    stream.read_line(&mut greeting).await?;
+    if !greeting.starts_with("OK MPD") {
+        return Err(anyhow!("unexpected mpd greeting: {}", greeting.trim()));
+    }
+
+    let status = command(&mut stream, "status").await?;
+    let song = command(&mut stream, "currentsong").await?;
+    let _ = stream.get_mut().write_all(b"close\n").await;
+
+    Ok((status, song))
+}
+
+#[async_trait]
+impl NowPlayingProvider for MpdProvider {
+    fn name(&self) -> &'static str {
+        "mpd"
+    }
+
+    fn source(&self) -> SourceApp {
+        SourceApp::Mpd
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            accurate_position: true,
+            ..ProviderCapabilities::default()
+        }
+    }
+
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+        let stream = match self.connect().await.map_err(ProviderError::from) {
+            Ok(stream) => stream,
+            Err(err) if means_no_server(&err) => {
+                debug!(error = %err, "mpd unavailable");
+                return Ok(ProviderSnapshot::stopped(self.name()));
+            }
+            Err(err) => return Err(err),
+        };
+        let (status, song) = self.query(stream).await?;
+        Ok(snapshot_from_responses(self.name(), &status, &song))
+    }
+}
+
+/// Connect failures that only mean no MPD server is reachable, the normal "nothing
+/// playing" case: refused, unroutable, unresolvable or timed out.
+fn means_no_server(err: &ProviderError) -> bool {
+    matches!(
+        err,
+        ProviderError::NotRunning(_) | ProviderError::Timeout(_) | ProviderError::Transport(_)
+    )
+}
+
+async fn command(stream: &mut BufReader<TcpStream>, cmd: &str) -> Result<String> {
+    stream
+        .get_mut()
+        .write_all(format!("{cmd}\n").as_bytes())
+        .await?;
+
+    let mut body = String::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("mpd closed the connection"));
+        }
+        if line == "OK\n" {
+            return Ok(body);
+        }
+        if line.starts_with("ACK") {
+            return Err(anyhow!("mpd {cmd} failed: {}", line.trim()));
+        }
+        body.push_str(&line);
+    }
+}
+
+fn parse_pairs(body: &str) -> HashMap<&str, &str> {
+    body.lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect()
+}
+
+fn seconds_to_ms(v: &str) -> Option<u64> {
+    let secs: f64 = v.parse().ok()?;
+    if secs.is_finite() && secs > 0.0 {
+        Some((secs * 1_000.0).round() as u64)
+    } else {
+        None
+    }
+}
+
+fn snapshot_from_responses(name: &'static str, status: &str, song: &str) -> ProviderSnapshot {
+    let status = parse_pairs(status);
+    let song = parse_pairs(song);
+
+    let state = status.get("state").copied().unwrap_or("stop");
+    let is_playing = match state {
+        "play" => true,
+        "pause" => false,
+        _ => return ProviderSnapshot::stopped(name),
+    };
+    let Some(file) = song.get("file").copied() else {
+        return ProviderSnapshot::stopped(name);
+    };
+
+    let title = song
+        .get("Title")
+        .map(|t| clean_metadata(t))
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| {
+            file.rsplit('/')
+                .next()
+                .map(clean_metadata)
+                .unwrap_or_else(|| "Unknown Title".to_string())
+        });
+    let artist = song
+        .get("Artist")
+        .map(|a| clean_metadata(a))
+        .filter(|a| !a.is_empty())
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+    let album = song
+        .get("Album")
+        .map(|a| clean_metadata(a))
+        .filter(|a| !a.is_empty());
+    let track_number = song
+        .get("Track")
+        .and_then(|v| v.split('/').next())
+        .and_then(|v| v.trim().parse().ok());
+    let year = song.get("Date").and_then(|v| parse_year(v));
+    let duration_ms = status
+        .get("duration")
+        .or_else(|| song.get("duration"))
+        .and_then(|v| seconds_to_ms(v));
+    let position_ms = status.get("elapsed").and_then(|v| seconds_to_ms(v));
+
+    let is_podcast = song.get("Genre").is_some_and(|g| is_podcast_genre(g));
+    let links = urls::track_links(&artist, &title, SearchQueryMode::Raw);
+
+    let track = Track {
+        id: file.to_string(),
+        title,
+        artist,
+        album,
+        track_number,
+        year,
+        duration_ms,
+        position_ms: clamp_position(position_ms, duration_ms),
+        is_playing,
+        source: SourceApp::Mpd,
+        player: None,
+        is_podcast,
+        links,
+        updated_at: SystemTime::now(),
+    };
+
+    ProviderSnapshot {
+        provider_name: name,
+        state: if is_playing {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Paused
+        },
+        track: Some(track),
+        raw_state: Some(state.to_string()),
+        last_error: None,
+        capabilities: ProviderCapabilities::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot_from_responses;
+    use super::{means_no_server, MpdProvider};
+    use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError};
+    use anyhow::Context;
+    use presence_bridge_core::PlaybackState;
+
+    const STATUS_PLAYING: &str = "volume: 80\nrepeat: 0\nrandom: 0\nstate: play\nsong: 3\nsongid: 4\ntime: 42:215\nelapsed: 42.318\nbitrate: 320\nduration: 215.040\n";
+    const CURRENT_SONG: &str = "file: music/Daft Punk/Random Access Memories/08 Get Lucky.flac\nArtist: Daft Punk\nTitle: Get Lucky\nAlbum: Random Access Memories\nTrack: 8/13\nDate: 2013-05-17\nTime: 215\nduration: 215.040\nPos: 3\nId: 4\n";
+
+    #[test]
+    fn parses_playing_song() {
+        let snap = snapshot_from_responses("mpd", STATUS_PLAYING, CURRENT_SONG);
+        assert_eq!(snap.state, PlaybackState::Playing);
+        let track = snap.track.expect("track");
+        assert_eq!(track.title, "Get Lucky");
+        assert_eq!(track.artist, "Daft Punk");
+        assert_eq!(track.album.as_deref(), Some("Random Access Memories"));
+        assert_eq!(track.track_number, Some(8));
+        assert_eq!(track.year, Some(2013));
+        assert_eq!(track.duration_ms, Some(215_040));
+        assert_eq!(track.position_ms, Some(42_318));
+        assert!(track.is_playing);
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            // Accepts, then never sends the greeting.
+            let (conn, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+            drop(conn);
+        });
+
+        let mut provider = MpdProvider::new("127.0.0.1".to_string(), port);
+        provider.io_timeout = std::time::Duration::from_millis(50);
+        let stream = provider.connect().await.expect("connect");
+        let err = provider.query(stream).await.unwrap_err();
+        server.abort();
+        assert!(err.to_string().contains("stopped responding"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn unreachable_or_timed_out_connects_read_as_stopped() {
+        // `.invalid` never resolves, so the connect fails without touching the network.
+        let mut provider = MpdProvider::new("mpd.invalid".to_string(), 6600);
+        let err = ProviderError::from(provider.connect().await.unwrap_err());
+        assert!(matches!(err, ProviderError::Transport(_)), "{err:?}");
+        let snapshot = provider.poll().await.expect("stopped, not an error");
+        assert_eq!(snapshot.state, PlaybackState::Stopped);
+
+        // A connect timeout keeps its `Elapsed` cause, so it classifies as a timeout.
+        let timed_out = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            std::future::pending::<()>(),
+        )
+        .await
+        .context("timed out connecting to mpd at mpd.invalid:6600")
+        .unwrap_err();
+        let err = ProviderError::from(timed_out);
+        assert!(matches!(err, ProviderError::Timeout(_)), "{err:?}");
+        assert!(means_no_server(&err));
+        assert!(!means_no_server(&ProviderError::PermissionDenied(
+            "sandboxed".to_string()
+        )));
+    }
+
+    #[test]
+    fn stopped_or_untagged_responses() {
+        let stopped = snapshot_from_responses("mpd", "volume: 80\nstate: stop\n", "");
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        assert!(stopped.track.is_none());
+
+        let untagged =
+            snapshot_from_responses("mpd", "state: pause\n", "file: radio/stream-1.mp3\n");
+        assert_eq!(untagged.state, PlaybackState::Paused);
+        let track = untagged.track.expect("track");
+        assert_eq!(track.title, "stream-1.mp3");
+        assert_eq!(track.artist, "Unknown Artist");
+    }
+
+    #[test]
+    fn podcast_genre_marks_an_episode() {
+        let episode = snapshot_from_responses(
+            "mpd",
+            STATUS_PLAYING,
+            "file: podcasts/show/episode-12.mp3\nTitle: Episode 12\nGenre: Podcast\n",
+        );
+        assert!(episode.track.expect("track").is_podcast);
+
+        let song = snapshot_from_responses("mpd", STATUS_PLAYING, CURRENT_SONG);
+        assert!(!song.track.expect("track").is_podcast);
+    }
+
+    #[test]
+    fn advertises_live_position_only() {
+        assert_eq!(
+            MpdProvider::new("localhost".to_string(), 6600).capabilities(),
+            ProviderCapabilities {
+                accurate_position: true,
+                ..ProviderCapabilities::default()
+            }
+        );
+    }
+}
@@ -1,20 +1,185 @@
-use crate::{NowPlayingProvider, ProviderSnapshot};
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use presence_bridge_core::{urls, PlaybackState, SourceApp, Track, TrackLinks};
-use std::time::SystemTime;
-use zbus::zvariant::{OwnedValue, Str};
-use zbus::{Connection, Proxy};
+use futures_util::StreamExt;
+use presence_bridge_core::text::{clean_metadata, parse_year, split_artist_title};
+use presence_bridge_core::{
+    clamp_position, is_podcast_genre, urls, PlaybackState, SearchQueryMode, SourceApp, Track,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::debug;
+use zbus::zvariant::{ObjectPath, OwnedValue, Str};
+use zbus::{Connection, Message, Proxy};
+
+/// How long a `Seeked` position is trusted over the polled `Position` property.
+const SEEK_TRUST_WINDOW: Duration = Duration::from_secs(5);
+/// A position this many times the track length can't be a small overshoot.
+const IMPLAUSIBLE_OVERSHOOT: u64 = 10;
+/// Without a length, anything past 30 days is taken to be in the wrong unit.
+const MAX_POSITION_MS: u64 = 30 * 24 * 60 * 60 * 1_000;
+/// A track shorter than this is taken to have its length in a coarser unit.
+const MIN_TRACK_LENGTH_MS: u64 = 1_000;
+
+/// The unit a player reports `mpris:length` and `Position` in; the spec says microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeUnit {
+    Micros,
+    Millis,
+    Secs,
+}
+
+impl TimeUnit {
+    fn to_ms(self, raw: u64) -> u64 {
+        match self {
+            Self::Micros => raw / 1_000,
+            Self::Millis => raw,
+            Self::Secs => raw.saturating_mul(1_000),
+        }
+    }
+
+    /// The finest unit, spec first, in which `length` is a believable track length.
+    fn from_length(length: u64) -> Self {
+        [Self::Micros, Self::Millis, Self::Secs]
+            .into_iter()
+            .find(|unit| unit.to_ms(length) >= MIN_TRACK_LENGTH_MS)
+            .unwrap_or(Self::Micros)
+    }
+}
+
+/// The exact position a player reported through its `Seeked` signal.
+#[derive(Debug, Clone)]
+struct SeekMark {
+    position_us: i64,
+    at: Instant,
+    /// The `mpris:trackid` playing when the seek arrived, so it isn't applied to the next track.
+    track_id: Option<String>,
+}
 
 #[derive(Default)]
-pub struct MprisProvider;
+pub struct MprisProvider {
+    last_seek: Arc<Mutex<Option<SeekMark>>>,
+    seek_listener: Option<(String, JoinHandle<()>)>,
+    filter: PlayerFilter,
+    title_separator: Option<String>,
+}
+
+/// Which bus names may be picked; a deny match always wins over an allow match.
+#[derive(Debug, Clone, Default)]
+struct PlayerFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl PlayerFilter {
+    fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        let lower = |patterns: Vec<String>| patterns.iter().map(|p| p.to_lowercase()).collect();
+        Self {
+            allow: lower(allow),
+            deny: lower(deny),
+        }
+    }
+
+    fn permits(&self, bus_name: &str) -> bool {
+        let name = bus_name.to_lowercase();
+        if self.deny.iter().any(|p| pattern_matches(p, &name)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|p| pattern_matches(p, &name))
+    }
+}
+
+/// A pattern with `*` is a glob over the whole name; anything else is a substring.
+fn pattern_matches(pattern: &str, name: &str) -> bool {
+    if !pattern.contains('*') {
+        return name.contains(pattern);
+    }
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The first permitted player in bus-name order.
+fn select_player(names: Vec<String>, filter: &PlayerFilter) -> Option<String> {
+    let mut players: Vec<String> = names
+        .into_iter()
+        .filter(|n| n.starts_with("org.mpris.MediaPlayer2."))
+        .filter(|n| filter.permits(n))
+        .collect();
+    players.sort();
+    players.into_iter().next()
+}
 
 impl MprisProvider {
     pub fn new() -> Self {
-        Self
+        Self::default()
     }
 
-    async fn find_player(conn: &Connection) -> Result<Option<String>> {
+    pub fn with_player_filter(mut self, allow: Vec<String>, deny: Vec<String>) -> Self {
+        self.filter = PlayerFilter::new(allow, deny);
+        self
+    }
+
+    /// Splits `"Artist<separator>Title"` titles from players that report no artist.
+    pub fn with_title_separator(mut self, separator: Option<String>) -> Self {
+        self.title_separator = separator.filter(|sep| !sep.is_empty());
+        self
+    }
+
+    /// Follows `Seeked` on the active player, restarting when the player changes.
+    fn watch_seeks(&mut self, player: &str, proxy: &Proxy<'static>) {
+        if matches!(&self.seek_listener, Some((name, task)) if name == player && !task.is_finished())
+        {
+            return;
+        }
+        if let Some((_, task)) = self.seek_listener.take() {
+            task.abort();
+        }
+        *self.last_seek.lock().unwrap() = None;
+
+        let proxy = proxy.clone();
+        let last_seek = self.last_seek.clone();
+        let task = tokio::spawn(async move {
+            let mut seeks = match proxy.receive_signal("Seeked").await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!(error = %err, "failed to subscribe to MPRIS Seeked");
+                    return;
+                }
+            };
+            while let Some(msg) = seeks.next().await {
+                if let Some(position_us) = seeked_position(&msg) {
+                    let at = Instant::now();
+                    let track_id = proxy
+                        .get_property::<HashMap<String, OwnedValue>>("Metadata")
+                        .await
+                        .ok()
+                        .and_then(|metadata| track_id(&metadata));
+                    *last_seek.lock().unwrap() = Some(SeekMark {
+                        position_us,
+                        at,
+                        track_id,
+                    });
+                }
+            }
+        });
+        self.seek_listener = Some((player.to_string(), task));
+    }
+
+    async fn find_player(&self, conn: &Connection) -> Result<Option<String>> {
         let proxy = Proxy::new(
             conn,
             "org.freedesktop.DBus",
@@ -24,12 +189,7 @@ impl MprisProvider {
         .await?;
 
         let names: Vec<String> = proxy.call("ListNames", &()).await?;
-        let mut players: Vec<String> = names
-            .into_iter()
-            .filter(|n| n.starts_with("org.mpris.MediaPlayer2."))
-            .collect();
-        players.sort();
-        Ok(players.into_iter().next())
+        Ok(select_player(names, &self.filter))
     }
 
     fn ov_to_string(v: &OwnedValue) -> Option<String> {
@@ -47,6 +207,9 @@ impl MprisProvider {
         if let Ok(i) = <i64>::try_from(v) {
             return Some(i);
         }
+        if let Ok(i) = <i32>::try_from(v) {
+            return Some(i as i64);
+        }
         if let Ok(u) = <u64>::try_from(v) {
             return Some(u as i64);
         }
@@ -71,11 +234,19 @@ impl NowPlayingProvider for MprisProvider {
         SourceApp::Mpris
     }
 
-    async fn poll(&mut self) -> Result<ProviderSnapshot> {
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            seek_signals: true,
+            accurate_position: true,
+            ..ProviderCapabilities::default()
+        }
+    }
+
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
         let conn = Connection::session()
             .await
             .context("failed to connect DBus session")?;
-        let player = match Self::find_player(&conn).await? {
+        let player = match self.find_player(&conn).await? {
             Some(p) => p,
             None => return Ok(ProviderSnapshot::stopped(self.name())),
         };
@@ -88,65 +259,555 @@ impl NowPlayingProvider for MprisProvider {
         )
         .await?;
 
+        self.watch_seeks(&player, &proxy);
+
         let status: String = proxy.get_property("PlaybackStatus").await?;
         if status == "Stopped" {
             return Ok(ProviderSnapshot::stopped(self.name()));
         }
 
-        let metadata: std::collections::HashMap<String, OwnedValue> =
-            proxy.get_property("Metadata").await?;
+        let metadata: HashMap<String, OwnedValue> = proxy.get_property("Metadata").await?;
+        let position_us: i64 = proxy.get_property("Position").await.unwrap_or(0);
+        let last_seek = self.last_seek.lock().unwrap().clone();
+        let position_us = corrected_position_us(
+            position_us,
+            last_seek.as_ref(),
+            track_id(&metadata).as_deref(),
+            status == "Playing",
+            Instant::now(),
+        );
 
-        let title = metadata
-            .get("xesam:title")
-            .and_then(Self::ov_to_string)
-            .unwrap_or_else(|| "Unknown Title".to_string());
-        let artist = metadata
-            .get("xesam:artist")
-            .and_then(Self::artist_from_value)
-            .unwrap_or_else(|| "Unknown Artist".to_string());
-        let album = metadata.get("xesam:album").and_then(Self::ov_to_string);
-        let duration_ms = metadata
-            .get("mpris:length")
-            .and_then(Self::ov_to_i64)
-            .map(|v| (v as u64) / 1_000);
+        Ok(snapshot_from_reading(
+            self.name(),
+            PlayerReading {
+                player,
+                status,
+                metadata,
+                position_us,
+            },
+            self.title_separator.as_deref(),
+        ))
+    }
+}
+
+/// What `poll` reads from the active player over DBus.
+struct PlayerReading {
+    player: String,
+    status: String,
+    metadata: HashMap<String, OwnedValue>,
+    position_us: i64,
+}
+
+/// Maps a player's status and `Metadata` to a snapshot, independent of DBus.
+fn snapshot_from_reading(
+    name: &'static str,
+    reading: PlayerReading,
+    title_separator: Option<&str>,
+) -> ProviderSnapshot {
+    let PlayerReading {
+        player,
+        status,
+        metadata,
+        position_us,
+    } = reading;
+    if status != "Playing" && status != "Paused" {
+        return ProviderSnapshot::stopped(name);
+    }
 
-        let position_raw: i64 = proxy.get_property("Position").await.unwrap_or(0);
+    let title = metadata
+        .get("xesam:title")
+        .and_then(MprisProvider::ov_to_string)
+        .map(|t| clean_metadata(&t))
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| "Unknown Title".to_string());
+    let artist = metadata
+        .get("xesam:artist")
+        .and_then(MprisProvider::artist_from_value)
+        .map(|a| clean_metadata(&a))
+        .filter(|a| !a.is_empty());
+    // Only a missing artist is worth the guess; real titles contain dashes too. Some
+    // players fill the gap with a literal "Unknown", which counts as missing here only.
+    let artist_missing = artist
+        .as_deref()
+        .is_none_or(|a| a.eq_ignore_ascii_case("unknown"));
+    let (artist, title) = title_separator
+        .filter(|_| artist_missing)
+        .and_then(|sep| split_artist_title(&title, sep))
+        .unwrap_or_else(|| {
+            let artist = artist.unwrap_or_else(|| "Unknown Artist".to_string());
+            (artist, title)
+        });
+    let album = metadata
+        .get("xesam:album")
+        .and_then(MprisProvider::ov_to_string)
+        .map(|a| clean_metadata(&a))
+        .filter(|a| !a.is_empty());
+    let track_number = metadata
+        .get("xesam:trackNumber")
+        .and_then(MprisProvider::ov_to_i64)
+        .and_then(|n| u32::try_from(n).ok())
+        .filter(|n| *n > 0);
+    let year = metadata
+        .get("xesam:contentCreated")
+        .and_then(MprisProvider::ov_to_string)
+        .and_then(|d| parse_year(&d));
+    let length = metadata
+        .get("mpris:length")
+        .and_then(MprisProvider::ov_to_i64)
+        .filter(|v| *v > 0)
+        .map(|v| v as u64);
+    let unit = length.map_or(TimeUnit::Micros, TimeUnit::from_length);
+    let duration_ms = length.map(|length| unit.to_ms(length));
+    let is_podcast = metadata
+        .get("xesam:genre")
+        .and_then(|v| Vec::<String>::try_from(v.try_clone().ok()?).ok())
+        .is_some_and(|genres| genres.iter().any(|g| is_podcast_genre(g)));
+    let position_ms = position_ms(&player, position_us, unit, duration_ms);
 
-        let position_ms = if position_raw > 0 {
-            Some((position_raw as u64) / 1_000)
+    let is_playing = status == "Playing";
+    let links = urls::track_links(&artist, &title, SearchQueryMode::Raw);
+
+    let track = Track {
+        id: format!("{}:{}", artist, title),
+        title,
+        artist,
+        album,
+        track_number,
+        year,
+        duration_ms,
+        position_ms: clamp_position(position_ms, duration_ms),
+        is_playing,
+        source: SourceApp::Mpris,
+        player: Some(player),
+        is_podcast,
+        links,
+        updated_at: SystemTime::now(),
+    };
+
+    ProviderSnapshot {
+        provider_name: name,
+        state: if is_playing {
+            PlaybackState::Playing
         } else {
-            None
+            PlaybackState::Paused
+        },
+        track: Some(track),
+        raw_state: Some(status),
+        last_error: None,
+        capabilities: ProviderCapabilities::default(),
+    }
+}
+
+impl Drop for MprisProvider {
+    fn drop(&mut self) {
+        if let Some((_, task)) = self.seek_listener.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Converts `Position` to milliseconds. The spec says microseconds, but some players use
+/// another unit. One whose `mpris:length` only makes sense in milliseconds or seconds is
+/// read in that unit for both, since `unit` comes from the length. A position far past the
+/// end of the track is retried as nanoseconds and dropped if it still doesn't fit, so it
+/// never becomes a bogus start timestamp.
+fn position_ms(
+    player: &str,
+    position: i64,
+    unit: TimeUnit,
+    duration_ms: Option<u64>,
+) -> Option<u64> {
+    if position <= 0 {
+        return None;
+    }
+    let raw = position as u64;
+    let limit = match duration_ms {
+        Some(duration) if duration > 0 => duration.saturating_mul(IMPLAUSIBLE_OVERSHOOT),
+        _ => MAX_POSITION_MS,
+    };
+    let scaled = unit.to_ms(raw);
+    if scaled <= limit {
+        if unit != TimeUnit::Micros {
+            debug!(
+                player,
+                raw,
+                ?unit,
+                "read MPRIS position in the unit of its length"
+            );
+        }
+        return Some(scaled);
+    }
+    let rescaled = raw / 1_000_000;
+    if rescaled <= limit {
+        debug!(
+            player,
+            raw,
+            position_ms = rescaled,
+            "rescaled implausible MPRIS position"
+        );
+        Some(rescaled)
+    } else {
+        debug!(
+            player,
+            raw,
+            ?duration_ms,
+            "dropped implausible MPRIS position"
+        );
+        None
+    }
+}
+
+/// `mpris:trackid`, an object path by the spec though some players send a string.
+fn track_id(metadata: &HashMap<String, OwnedValue>) -> Option<String> {
+    let value = metadata.get("mpris:trackid")?;
+    if let Ok(path) = <&ObjectPath>::try_from(&**value) {
+        return Some(path.to_string());
+    }
+    MprisProvider::ov_to_string(value)
+}
+
+/// Reads the position, in microseconds, carried by a `Seeked` signal.
+fn seeked_position(msg: &Message) -> Option<i64> {
+    msg.body().deserialize::<i64>().ok()
+}
+
+/// Prefers a recent `Seeked` position on the same track, advanced by the time since while
+/// playing; some players only refresh the `Position` property lazily after a seek.
+fn corrected_position_us(
+    polled_us: i64,
+    last_seek: Option<&SeekMark>,
+    track_id: Option<&str>,
+    is_playing: bool,
+    now: Instant,
+) -> i64 {
+    match last_seek {
+        Some(mark)
+            if mark.track_id.as_deref() == track_id
+                && now.saturating_duration_since(mark.at) <= SEEK_TRUST_WINDOW =>
+        {
+            let elapsed = if is_playing {
+                now.saturating_duration_since(mark.at).as_micros() as i64
+            } else {
+                0
+            };
+            mark.position_us.saturating_add(elapsed)
+        }
+        _ => polled_us,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MprisProvider;
+    use super::{
+        corrected_position_us, position_ms, seeked_position, select_player, snapshot_from_reading,
+        track_id, PlayerFilter, PlayerReading, SeekMark, TimeUnit,
+    };
+    use crate::{NowPlayingProvider, ProviderCapabilities};
+    use presence_bridge_core::PlaybackState;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+    use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+    use zbus::Message;
+
+    fn reading(status: &str, fields: Vec<(&str, Value<'static>)>) -> PlayerReading {
+        PlayerReading {
+            player: "org.mpris.MediaPlayer2.spotify".to_string(),
+            status: status.to_string(),
+            metadata: fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), OwnedValue::try_from(v).expect("owned")))
+                .collect::<HashMap<_, _>>(),
+            position_us: 30_000_000,
+        }
+    }
+
+    #[test]
+    fn maps_full_metadata() {
+        let snapshot = snapshot_from_reading(
+            "mpris",
+            reading(
+                "Playing",
+                vec![
+                    ("xesam:title", Value::from("Get Lucky")),
+                    ("xesam:artist", Value::from(vec!["Daft Punk", "Pharrell"])),
+                    ("xesam:album", Value::from("Random Access Memories")),
+                    ("xesam:trackNumber", Value::from(8_i32)),
+                    ("xesam:contentCreated", Value::from("2013-05-17T00:00:00Z")),
+                    ("mpris:length", Value::from(248_000_000_i64)),
+                ],
+            ),
+            None,
+        );
+        assert_eq!(snapshot.state, PlaybackState::Playing);
+        assert_eq!(snapshot.raw_state.as_deref(), Some("Playing"));
+        let track = snapshot.track.expect("track");
+        assert_eq!(track.id, "Daft Punk:Get Lucky");
+        assert_eq!(track.album.as_deref(), Some("Random Access Memories"));
+        assert_eq!((track.track_number, track.year), (Some(8), Some(2013)));
+        assert_eq!(track.duration_ms, Some(248_000));
+        assert_eq!(track.position_ms, Some(30_000));
+        assert_eq!(
+            track.player.as_deref(),
+            Some("org.mpris.MediaPlayer2.spotify")
+        );
+        assert!(!track.is_podcast);
+    }
+
+    #[test]
+    fn maps_sparse_and_odd_metadata() {
+        // Browsers often send a title only, with the length as u64.
+        let snapshot = snapshot_from_reading(
+            "mpris",
+            reading(
+                "Paused",
+                vec![
+                    ("xesam:title", Value::from("A video")),
+                    ("xesam:artist", Value::from(Vec::<String>::new())),
+                    ("xesam:album", Value::from("")),
+                    ("mpris:length", Value::from(10_000_000_u64)),
+                ],
+            ),
+            None,
+        );
+        assert_eq!(snapshot.state, PlaybackState::Paused);
+        let track = snapshot.track.expect("track");
+        assert!(!track.is_playing);
+        assert_eq!(track.artist, "Unknown Artist");
+        assert_eq!(track.album, None);
+        assert_eq!(track.position_ms, Some(10_000));
+
+        let empty = snapshot_from_reading("mpris", reading("Playing", Vec::new()), None);
+        assert_eq!(empty.track.expect("track").title, "Unknown Title");
+
+        let stopped = snapshot_from_reading(
+            "mpris",
+            reading("Stopped", vec![("xesam:title", Value::from("Old"))]),
+            None,
+        );
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        assert!(stopped.track.is_none());
+    }
+
+    #[test]
+    fn podcast_genre_marks_an_episode() {
+        let is_podcast = |genres: Vec<&'static str>| {
+            let reading = reading(
+                "Playing",
+                vec![
+                    ("xesam:title", Value::from("Episode 12")),
+                    ("xesam:genre", Value::from(genres)),
+                ],
+            );
+            snapshot_from_reading("mpris", reading, None)
+                .track
+                .expect("track")
+                .is_podcast
         };
+        assert!(is_podcast(vec!["News", "Podcast"]));
+        assert!(!is_podcast(vec!["Electronic"]));
+    }
 
-        let is_playing = status == "Playing";
-        let links = TrackLinks {
-            apple_music: Some(urls::apple_music_search_url(&artist, &title)),
-            spotify_search: Some(urls::spotify_search_url(&artist, &title)),
+    #[test]
+    fn splits_artist_out_of_the_title_only_when_missing() {
+        let title_only = |artist: Option<&'static str>| {
+            let mut metadata = vec![("xesam:title", Value::from("Daft Punk - Get Lucky"))];
+            if let Some(artist) = artist {
+                metadata.push(("xesam:artist", Value::from(vec![artist])));
+            }
+            let track = snapshot_from_reading("mpris", reading("Playing", metadata), Some(" - "))
+                .track
+                .expect("track");
+            (track.artist, track.title)
         };
 
-        let track = Track {
-            id: format!("{}:{}", artist, title),
-            title,
-            artist,
-            album,
-            duration_ms,
-            position_ms,
-            is_playing,
-            source: SourceApp::Mpris,
-            links,
-            updated_at: SystemTime::now(),
+        let split = ("Daft Punk".to_string(), "Get Lucky".to_string());
+        assert_eq!(title_only(None), split);
+        assert_eq!(title_only(Some("Unknown")), split);
+        assert_eq!(
+            title_only(Some("The Band")),
+            ("The Band".to_string(), "Daft Punk - Get Lucky".to_string())
+        );
+
+        let unset = |artist: Option<&'static str>| {
+            let mut metadata = vec![("xesam:title", Value::from("Daft Punk - Get Lucky"))];
+            if let Some(artist) = artist {
+                metadata.push(("xesam:artist", Value::from(vec![artist])));
+            }
+            snapshot_from_reading("mpris", reading("Playing", metadata), None)
+                .track
+                .expect("track")
+                .artist
         };
+        assert_eq!(unset(None), "Unknown Artist");
+        // Without splitting, an artist named "Unknown" is just an artist.
+        assert_eq!(unset(Some("Unknown")), "Unknown");
+    }
 
-        Ok(ProviderSnapshot {
-            provider_name: self.name(),
-            state: if is_playing {
-                PlaybackState::Playing
-            } else {
-                PlaybackState::Paused
-            },
-            track: Some(track),
-            raw_state: Some(status),
-            last_error: None,
-        })
+    #[test]
+    fn allow_and_deny_lists_pick_the_player() {
+        let names = || {
+            [
+                "org.freedesktop.Notifications",
+                "org.mpris.MediaPlayer2.chromium.instance4321",
+                "org.mpris.MediaPlayer2.firefox.instance_1_23",
+                "org.mpris.MediaPlayer2.spotify",
+                "org.mpris.MediaPlayer2.vlc",
+            ]
+            .map(String::from)
+            .to_vec()
+        };
+        let pick = |allow: &[&str], deny: &[&str]| {
+            let filter = PlayerFilter::new(
+                allow.iter().map(|s| s.to_string()).collect(),
+                deny.iter().map(|s| s.to_string()).collect(),
+            );
+            select_player(names(), &filter)
+        };
+
+        assert_eq!(
+            pick(&[], &[]).as_deref(),
+            Some("org.mpris.MediaPlayer2.chromium.instance4321")
+        );
+        assert_eq!(
+            pick(&["Spotify"], &[]).as_deref(),
+            Some("org.mpris.MediaPlayer2.spotify")
+        );
+        assert_eq!(
+            pick(&[], &["chromium", "*.firefox.*"]).as_deref(),
+            Some("org.mpris.MediaPlayer2.spotify")
+        );
+        assert_eq!(
+            pick(&["*firefox*", "vlc"], &[]).as_deref(),
+            Some("org.mpris.MediaPlayer2.firefox.instance_1_23")
+        );
+        assert_eq!(
+            pick(&["spotify", "vlc"], &["spotify"]).as_deref(),
+            Some("org.mpris.MediaPlayer2.vlc")
+        );
+        assert_eq!(pick(&["spotify"], &["spotify"]), None);
+        assert_eq!(pick(&["org.mpris.*.mpd"], &[]), None);
+    }
+
+    #[test]
+    fn mis_scaled_positions_are_rescaled_or_dropped() {
+        let mut ns = reading(
+            "Playing",
+            vec![
+                ("xesam:title", Value::from("Get Lucky")),
+                ("mpris:length", Value::from(248_000_000_i64)),
+            ],
+        );
+        ns.position_us = 30_000_000_000;
+        let track = snapshot_from_reading("mpris", ns, None)
+            .track
+            .expect("track");
+        assert_eq!(track.position_ms, Some(30_000));
+
+        // Slightly past the end is still clamped rather than treated as another unit.
+        assert_eq!(
+            position_ms("vlc", 250_000_000, TimeUnit::Micros, Some(248_000)),
+            Some(250_000)
+        );
+        assert_eq!(
+            position_ms("vlc", i64::MAX, TimeUnit::Micros, Some(248_000)),
+            None
+        );
+        assert_eq!(position_ms("vlc", i64::MAX, TimeUnit::Micros, None), None);
+        assert_eq!(
+            position_ms("vlc", 90_000_000_000_000, TimeUnit::Micros, None),
+            Some(90_000_000)
+        );
+        assert_eq!(position_ms("vlc", 0, TimeUnit::Micros, Some(248_000)), None);
+    }
+
+    #[test]
+    fn coarse_units_follow_the_track_length() {
+        let in_unit = |length: i64, position: i64| {
+            let mut reading = reading(
+                "Playing",
+                vec![
+                    ("xesam:title", Value::from("Get Lucky")),
+                    ("mpris:length", Value::from(length)),
+                ],
+            );
+            reading.position_us = position;
+            let track = snapshot_from_reading("mpris", reading, None)
+                .track
+                .expect("track");
+            (track.duration_ms, track.position_ms)
+        };
+
+        let expected = (Some(248_000), Some(42_000));
+        assert_eq!(in_unit(248_000_000, 42_000_000), expected);
+        assert_eq!(in_unit(248_000, 42_000), expected);
+        assert_eq!(in_unit(248, 42), expected);
+
+        assert_eq!(TimeUnit::from_length(248_000_000), TimeUnit::Micros);
+        assert_eq!(TimeUnit::from_length(248_000), TimeUnit::Millis);
+        assert_eq!(TimeUnit::from_length(248), TimeUnit::Secs);
+        // Too short in every unit: keep the spec's and let the length speak for itself.
+        assert_eq!(TimeUnit::from_length(0), TimeUnit::Micros);
+    }
+
+    #[test]
+    fn recent_seek_overrides_polled_position() {
+        let msg = Message::signal(
+            "/org/mpris/MediaPlayer2",
+            "org.mpris.MediaPlayer2.Player",
+            "Seeked",
+        )
+        .expect("builder")
+        .build(&(90_000_000_i64,))
+        .expect("message");
+        let track = Some("/org/mpris/MediaPlayer2/Track/1");
+        let mark = SeekMark {
+            position_us: seeked_position(&msg).expect("position"),
+            at: Instant::now(),
+            track_id: track.map(String::from),
+        };
+        let now = mark.at + Duration::from_secs(2);
+
+        assert_eq!(
+            corrected_position_us(10_000_000, Some(&mark), track, true, now),
+            92_000_000
+        );
+        assert_eq!(
+            corrected_position_us(10_000_000, Some(&mark), track, false, now),
+            90_000_000
+        );
+        let stale = mark.at + Duration::from_secs(30);
+        assert_eq!(
+            corrected_position_us(120_000_000, Some(&mark), track, true, stale),
+            120_000_000
+        );
+        assert_eq!(
+            corrected_position_us(10_000_000, None, track, true, now),
+            10_000_000
+        );
+        // A seek on the previous track says nothing about the new one.
+        let next = Some("/org/mpris/MediaPlayer2/Track/2");
+        assert_eq!(
+            corrected_position_us(1_000_000, Some(&mark), next, true, now),
+            1_000_000
+        );
+
+        let path = ObjectPath::try_from("/org/mpris/MediaPlayer2/Track/1").expect("path");
+        let as_path = reading("Playing", vec![("mpris:trackid", Value::from(path))]);
+        assert_eq!(track_id(&as_path.metadata).as_deref(), track);
+        let as_string = reading("Playing", vec![("mpris:trackid", Value::from("spotify:1"))]);
+        assert_eq!(track_id(&as_string.metadata).as_deref(), Some("spotify:1"));
+    }
+
+    #[test]
+    fn advertises_seek_signals_and_live_position() {
+        assert_eq!(
+            MprisProvider::new().capabilities(),
+            ProviderCapabilities {
+                seek_signals: true,
+                art_url: false,
+                accurate_position: true,
+            }
+        );
     }
 }
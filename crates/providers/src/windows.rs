@@ -1,8 +1,11 @@
-use crate::{NowPlayingProvider, ProviderSnapshot};
-use anyhow::Result;
+use crate::{NowPlayingProvider, ProviderCapabilities, ProviderError, ProviderSnapshot};
 use async_trait::async_trait;
-use presence_bridge_core::{urls, PlaybackState, SourceApp, Track, TrackLinks};
+use presence_bridge_core::text::clean_metadata;
+use presence_bridge_core::{
+    clamp_position, urls, PlaybackState, SearchQueryMode, SourceApp, Track,
+};
 use std::time::SystemTime;
+use tracing::debug;
 use windows::Media::Control::{
     GlobalSystemMediaTransportControlsSessionManager,
     GlobalSystemMediaTransportControlsSessionPlaybackStatus,
@@ -11,12 +14,35 @@ use windows::Media::Control::{
 #[derive(Default)]
 pub struct WindowsGsmtcProvider;
 
+/// HRESULTs GSMTC returns while the media service is still starting after login.
+const TRANSIENT_HRESULTS: [u32; 4] = [
+    0x8000_4005, // E_FAIL
+    0x8001_0108, // RPC_E_DISCONNECTED
+    0x8007_06BA, // RPC_S_SERVER_UNAVAILABLE
+    0x8007_0015, // ERROR_NOT_READY
+];
+
 impl WindowsGsmtcProvider {
     pub fn new() -> Self {
         Self
     }
 }
 
+fn is_transient_hresult(code: i32) -> bool {
+    TRANSIENT_HRESULTS.contains(&(code as u32))
+}
+
+/// Turns an AppUserModelID such as `Spotify.exe` or
+/// `Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic` into a readable app name.
+fn app_display_name(aumid: &str) -> String {
+    let app = aumid.rsplit('!').next().unwrap_or(aumid);
+    let app = app
+        .strip_suffix(".exe")
+        .or_else(|| app.strip_suffix(".EXE"))
+        .unwrap_or(app);
+    app.rsplit('.').next().unwrap_or(app).to_string()
+}
+
 #[async_trait]
 impl NowPlayingProvider for WindowsGsmtcProvider {
     fn name(&self) -> &'static str {
@@ -27,7 +53,26 @@ impl NowPlayingProvider for WindowsGsmtcProvider {
         SourceApp::WindowsMediaSession
     }
 
-    async fn poll(&mut self) -> Result<ProviderSnapshot> {
+    async fn poll(&mut self) -> Result<ProviderSnapshot, ProviderError> {
+        match self.query() {
+            Ok(snapshot) => Ok(snapshot),
+            // An error snapshot lets the engine hold the current presence instead of clearing.
+            Err(err) if is_transient_hresult(err.code().0) => {
+                debug!(hresult = format_args!("{:#010x}", err.code().0 as u32), error = %err, "windows media session not ready");
+                Ok(ProviderSnapshot::with_error(
+                    self.name(),
+                    ProviderError::Transport(
+                        "Windows media session service is not ready".to_string(),
+                    ),
+                ))
+            }
+            Err(err) => Err(ProviderError::Transport(err.to_string())),
+        }
+    }
+}
+
+impl WindowsGsmtcProvider {
+    fn query(&self) -> windows::core::Result<ProviderSnapshot> {
         let manager = GlobalSystemMediaTransportControlsSessionManager::RequestAsync()?.get()?;
         let session = match manager.GetCurrentSession() {
             Ok(s) => s,
@@ -38,9 +83,19 @@ impl NowPlayingProvider for WindowsGsmtcProvider {
         let playback = session.GetPlaybackInfo()?;
         let timeline = session.GetTimelineProperties()?;
 
-        let title = props.Title()?.to_string_lossy();
-        let artist = props.Artist()?.to_string_lossy();
-        let album = props.AlbumTitle()?.to_string_lossy();
+        let player = session
+            .SourceAppUserModelId()
+            .ok()
+            .map(|id| id.to_string_lossy())
+            .filter(|id| !id.is_empty());
+        let title = clean_metadata(&props.Title()?.to_string_lossy());
+        let mut artist = clean_metadata(&props.Artist()?.to_string_lossy());
+        let album = clean_metadata(&props.AlbumTitle()?.to_string_lossy());
+        let track_number = props
+            .TrackNumber()
+            .ok()
+            .and_then(|n| u32::try_from(n).ok())
+            .filter(|n| *n > 0);
         let status = playback.PlaybackStatus()?;
 
         let is_playing = status == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing;
@@ -66,22 +121,27 @@ impl NowPlayingProvider for WindowsGsmtcProvider {
         if title.is_empty() && artist.is_empty() {
             return Ok(ProviderSnapshot::stopped(self.name()));
         }
+        // Podcasts and browsers often leave the artist empty; name the app instead.
+        if artist.is_empty() {
+            artist = player.as_deref().map(app_display_name).unwrap_or_default();
+        }
 
-        let links = TrackLinks {
-            apple_music: Some(urls::apple_music_search_url(&artist, &title)),
-            spotify_search: Some(urls::spotify_search_url(&artist, &title)),
-        };
+        let links = urls::track_links(&artist, &title, SearchQueryMode::Raw);
 
         let track = Track {
             id: format!("{}:{}:{}", artist, title, album),
             title,
             artist,
             album: if album.is_empty() { None } else { Some(album) },
+            track_number,
+            year: None,
             duration_ms,
-            position_ms,
+            position_ms: clamp_position(position_ms, duration_ms),
             is_playing,
             source: SourceApp::WindowsMediaSession,
+            player,
             links,
+            is_podcast: false,
             updated_at: SystemTime::now(),
         };
 
@@ -91,6 +151,41 @@ impl NowPlayingProvider for WindowsGsmtcProvider {
             track: Some(track),
             raw_state: Some(format!("{status:?}")),
             last_error: None,
+            capabilities: ProviderCapabilities::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::WindowsGsmtcProvider;
+    use super::{app_display_name, is_transient_hresult};
+    use crate::{NowPlayingProvider, ProviderCapabilities};
+
+    #[test]
+    fn maps_startup_hresults_as_transient() {
+        assert!(is_transient_hresult(0x8000_4005_u32 as i32));
+        assert!(is_transient_hresult(0x8001_0108_u32 as i32));
+        assert!(!is_transient_hresult(0x8007_0005_u32 as i32)); // E_ACCESSDENIED
+        assert!(!is_transient_hresult(0));
+    }
+
+    #[test]
+    fn derives_app_name_from_aumid() {
+        assert_eq!(app_display_name("Spotify.exe"), "Spotify");
+        assert_eq!(
+            app_display_name("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic"),
+            "ZuneMusic"
+        );
+        assert_eq!(app_display_name("Chrome"), "Chrome");
+    }
+
+    #[test]
+    fn advertises_nothing() {
+        // GSMTC timeline positions are only refreshed on state changes.
+        assert_eq!(
+            WindowsGsmtcProvider::new().capabilities(),
+            ProviderCapabilities::default()
+        );
+    }
+}